@@ -1,97 +1,3284 @@
 #![allow(dead_code)]
 
+use std::fmt;
+use std::io::{self, IsTerminal, Read, Write};
+use std::time::{Duration, Instant};
+
 const MEM_SIZE: usize = 65535;
 
-fn interpret(code: String) -> () {
-    let mut memory: [u8; 65535] = [0; 65535];
+const HELLO_WORLD: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Add(i32),
+    Move(isize),
+    Output,
+    Input,
+    JumpIfZero(usize),
+    JumpIfNonZero(usize),
+    Clear,
+    Scan(isize),
+    MulAdd(isize, i32),
+    Breakpoint,
+    End,
+    Store,
+    Load,
+    ShiftLeft,
+    ShiftRight,
+    Not,
+    Xor,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Span {
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Program {
+    ops: Vec<Op>,
+    spans: Vec<Span>,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Bracket {
+    symbol: char,
+    line: usize,
+    column: usize,
+}
+
+#[derive(Debug)]
+enum BrainfuckError {
+    Unbalanced(Vec<Bracket>),
+    CellOverflow(usize),
+    PointerOutOfRange(isize),
+    StepLimitExceeded(u64),
+    Untranslatable(char),
+    UnknownDialect(String),
+    Syntax(usize, String),
+    Io(io::Error),
+}
+
+impl fmt::Display for BrainfuckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BrainfuckError::Unbalanced(brackets) => write!(f, "unbalanced brackets: {}", brackets
+                .iter()
+                .map(|b| format!("'{}' at {}:{}", b.symbol, b.line, b.column))
+                .collect::<Vec<String>>()
+                .join(", ")),
+            BrainfuckError::CellOverflow(at) => write!(f, "cell overflow at {}", at),
+            BrainfuckError::PointerOutOfRange(target) => write!(f, "pointer out of range: {}", target),
+            BrainfuckError::StepLimitExceeded(limit) => write!(f, "step limit of {} exceeded", limit),
+            BrainfuckError::Untranslatable(inst) => write!(f, "'{}' has no equivalent in the target dialect", inst),
+            BrainfuckError::UnknownDialect(name) => write!(f, "unknown dialect: {}", name),
+            BrainfuckError::Syntax(line, message) => write!(f, "syntax error on line {}: {}", line, message),
+            BrainfuckError::Io(err) => write!(f, "io error: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for BrainfuckError {
+    fn from(err: io::Error) -> Self {
+        return BrainfuckError::Io(err);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EofPolicy {
+    Unchanged,
+    Zero,
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CellWidth {
+    U8,
+    U16,
+    U32,
+}
+
+impl CellWidth {
+    fn max(&self) -> u32 {
+        return match self {
+            CellWidth::U8 => u8::MAX as u32,
+            CellWidth::U16 => u16::MAX as u32,
+            CellWidth::U32 => u32::MAX,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TapeSize {
+    Fixed(usize),
+    Dynamic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overflow {
+    Wrap,
+    Saturate,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Raw,
+    Decimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InstructionSet {
+    Classic,
+    Extended,
+}
+
+impl InstructionSet {
+    fn symbols(&self) -> &'static str {
+        return match self {
+            InstructionSet::Classic => "+-<>.,[]",
+            InstructionSet::Extended => "+-<>.,[]@$!{}~^&|",
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Config {
+    instructions: InstructionSet,
+    cell: CellWidth,
+    tape: TapeSize,
+    cell_overflow: Overflow,
+    pointer_overflow: Overflow,
+    eof: EofPolicy,
+    output: OutputFormat,
+    step_limit: Option<u64>,
+    // `#` is a comment like any other character unless a debugger asks for it as a breakpoint.
+    breakpoints: bool,
+}
+
+impl Config {
+    // Whether moving away and back always returns the pointer to where it started. On a dynamic tape a wrap off the left
+    // edge lands on whatever the right end is at the time, and that end moves as the tape grows.
+    fn moves_cancel(&self) -> bool {
+        return self.pointer_overflow == Overflow::Wrap && self.tape != TapeSize::Dynamic;
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        return Config {
+            instructions: InstructionSet::Classic,
+            cell: CellWidth::U8,
+            tape: TapeSize::Fixed(MEM_SIZE),
+            cell_overflow: Overflow::Wrap,
+            pointer_overflow: Overflow::Wrap,
+            eof: EofPolicy::Unchanged,
+            output: OutputFormat::Raw,
+            step_limit: None,
+            breakpoints: false,
+        };
+    }
+}
+
+struct Machine {
+    config: Config,
+    tape: Vec<u32>,
+    ptr: usize,
+    storage: u32,
+}
+
+impl Machine {
+    fn new(config: Config) -> Self {
+        let len = match config.tape {
+            TapeSize::Fixed(len) => len,
+            TapeSize::Dynamic => 1024,
+        };
+
+        return Machine { config, tape: vec![0; len.max(1)], ptr: 0, storage: 0 };
+    }
+
+    // One byte per cell from cell 0, growing the tape if it has to. `check_data` makes sure a fixed tape is long enough.
+    fn load(&mut self, data: &[u8]) -> () {
+        if data.len() > self.tape.len() {
+            self.tape.resize(data.len(), 0);
+        }
+
+        for (cell, &byte) in self.tape.iter_mut().zip(data) {
+            *cell = byte as u32;
+        }
+    }
+
+    fn get(&self) -> u32 {
+        return self.tape[self.ptr];
+    }
+
+    fn set(&mut self, value: u32) -> () {
+        self.tape[self.ptr] = value & self.config.cell.max();
+    }
+
+    fn add(&mut self, at: usize, n: i64) -> Result<(), BrainfuckError> {
+        let max = self.config.cell.max();
+
+        let value = self.tape[at] as i64;
+
+        self.tape[at] = match self.config.cell_overflow {
+            Overflow::Wrap => value.wrapping_add(n) as u32 & max,
+            Overflow::Saturate => value.saturating_add(n).clamp(0, max as i64) as u32,
+            Overflow::Error => match value.checked_add(n) {
+                Some(v) if v >= 0 && v <= max as i64 => v as u32,
+                _ => return Err(BrainfuckError::CellOverflow(at)),
+            },
+        };
+
+        return Ok(());
+    }
+
+    fn offset(&mut self, by: isize) -> Result<usize, BrainfuckError> {
+        let target = self.ptr as isize + by;
 
-    let mut ptr: usize = 0;
+        let len = self.tape.len() as isize;
 
-    let mut c = 0;
+        if target >= 0 && target < len {
+            return Ok(target as usize);
+        }
+
+        if target >= len && self.config.tape == TapeSize::Dynamic {
+            self.tape.resize((target as usize + 1).max(self.tape.len() * 2), 0);
+
+            return Ok(target as usize);
+        }
+
+        return match self.config.pointer_overflow {
+            Overflow::Wrap => Ok(target.rem_euclid(len) as usize),
+            Overflow::Saturate => Ok(target.clamp(0, len - 1) as usize),
+            Overflow::Error => Err(BrainfuckError::PointerOutOfRange(target)),
+        };
+    }
+
+    fn move_by(&mut self, by: isize) -> Result<(), BrainfuckError> {
+        self.ptr = self.offset(by)?;
+
+        return Ok(());
+    }
+}
+
+fn validate(code: &str) -> Result<(), BrainfuckError> {
+    let mut opens = vec![];
+
+    let mut unbalanced = vec![];
+
+    for (line, text) in code.lines().enumerate() {
+        for (column, symbol) in text.chars().enumerate() {
+            let bracket = Bracket { symbol, line: line + 1, column: column + 1 };
+
+            if symbol == '[' {
+                opens.push(bracket);
+            }
+
+            if symbol == ']' && opens.pop().is_none() {
+                unbalanced.push(bracket);
+            }
+        }
+    }
+
+    if opens.is_empty() && unbalanced.is_empty() {
+        return Ok(());
+    }
+
+    unbalanced.extend(opens);
+
+    unbalanced.sort_by_key(|b| (b.line, b.column));
+
+    return Err(BrainfuckError::Unbalanced(unbalanced));
+}
+
+fn line_column(before: &[char]) -> (usize, usize) {
+    let line = before.iter().filter(|c| **c == '\n').count() + 1;
+
+    let column = before.iter().rev().take_while(|c| **c != '\n').count() + 1;
+
+    return (line, column);
+}
+
+fn parse(code: &str, config: &Config) -> Result<Program, BrainfuckError> {
+    let (code, data) = match config.instructions {
+        InstructionSet::Classic => (code, ""),
+        InstructionSet::Extended => code.split_at(code.find('@').map_or(code.len(), |at| at + 1)),
+    };
+
+    validate(code)?;
+
+    let symbols = config.instructions.symbols();
+
+    let code: Vec<(usize, char)> = code.chars().enumerate().filter(|(_, c)| symbols.contains(*c) || config.breakpoints && *c == '#').collect();
+
+    let mut program = Program { ops: vec![], spans: vec![], data: data.bytes().collect() };
+
+    let mut opens = vec![];
 
     let mut i = 0;
 
     while i < code.len() {
-        let inst = code.chars().nth(i).unwrap();
+        let inst = code[i].1;
+
+        let mut run = 1;
+
+        while i + run < code.len() && code[i + run].1 == inst && "+-<>".contains(inst) {
+            run += 1;
+        }
+
+        let span = Span { start: code[i].0, end: code[i + run - 1].0 + 1 };
+
+        let op = match inst {
+            '+' => Op::Add(run as i32),
+            '-' => Op::Add(-(run as i32)),
+            '>' => Op::Move(run as isize),
+            '<' => Op::Move(-(run as isize)),
+            '.' => Op::Output,
+            ',' => Op::Input,
+            '#' => Op::Breakpoint,
+            '@' => Op::End,
+            '$' => Op::Store,
+            '!' => Op::Load,
+            '{' => Op::ShiftLeft,
+            '}' => Op::ShiftRight,
+            '~' => Op::Not,
+            '^' => Op::Xor,
+            '&' => Op::And,
+            '|' => Op::Or,
+            '[' => {
+                opens.push(program.ops.len());
+
+                Op::JumpIfZero(0)
+            }
+            _ => {
+                let open = opens.pop().unwrap();
+
+                let span = Span { start: program.spans[open].start, end: span.end };
+
+                if let Some(folded) = fold_loop(&program.ops[open + 1..], config) {
+                    program.ops.truncate(open);
+                    program.spans.truncate(open);
+
+                    for op in folded {
+                        program.ops.push(op);
+                        program.spans.push(span);
+                    }
+
+                    i += run;
+
+                    continue;
+                }
+
+                program.ops[open] = Op::JumpIfZero(program.ops.len());
 
-        if inst == '>' {
-            if ptr == MEM_SIZE - 1 {
-                ptr = 0;
-            } else {
-                ptr += 1;
+                Op::JumpIfNonZero(open)
             }
+        };
+
+        program.ops.push(op);
+        program.spans.push(span);
+
+        i += run;
+    }
+
+    return Ok(program);
+}
+
+fn fold_loop(body: &[Op], config: &Config) -> Option<Vec<Op>> {
+    if body == [Op::Add(-1)] {
+        return Some(vec![Op::Clear]);
+    }
+
+    if let [Op::Move(n)] = body {
+        return Some(vec![Op::Scan(*n)]);
+    }
+
+    if !config.moves_cancel() {
+        return None;
+    }
+
+    let mut offset = 0;
+
+    let mut deltas: Vec<(isize, i32)> = vec![];
+
+    for op in body {
+        match *op {
+            Op::Add(n) => match deltas.iter_mut().find(|(o, _)| *o == offset) {
+                Some((_, d)) => *d += n,
+                None => deltas.push((offset, n)),
+            },
+            Op::Move(n) => offset += n,
+            _ => return None,
         }
+    }
+
+    if offset != 0 || !deltas.contains(&(0, -1)) {
+        return None;
+    }
+
+    let mut folded: Vec<Op> = deltas
+        .into_iter()
+        .filter(|&(o, d)| o != 0 && d != 0)
+        .map(|(o, d)| Op::MulAdd(o, d))
+        .collect();
+
+    folded.push(Op::Clear);
+
+    return Some(folded);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LoopKind {
+    Dead,
+    Clear,
+    Scan(isize),
+    Multiply,
+    Balanced,
+    Unbalanced(isize),
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LoopFact {
+    line: usize,
+    column: usize,
+    kind: LoopKind,
+}
+
+impl fmt::Display for LoopFact {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{} ", self.line, self.column)?;
+
+        return match self.kind {
+            LoopKind::Dead => write!(f, "dead loop, the cell is always zero here"),
+            LoopKind::Clear => write!(f, "clears the cell"),
+            LoopKind::Scan(n) => write!(f, "scans for a zero cell in steps of {}", n),
+            LoopKind::Multiply => write!(f, "multiply loop"),
+            LoopKind::Balanced => write!(f, "balanced loop, the pointer returns to start"),
+            LoopKind::Unbalanced(n) => write!(f, "unbalanced loop, the pointer moves {} per iteration", n),
+            LoopKind::Unknown => write!(f, "unbalanced loop, the pointer moves by a data-dependent amount"),
+        };
+    }
+}
+
+// Loops, clears and scans all leave the current cell at zero, and so does the start of the program unless its data fills
+// the first cell.
+fn ends_loop(op: Option<&Op>, data: &[u8]) -> bool {
+    return match op {
+        None => data.first().is_none_or(|&byte| byte == 0),
+        Some(op) => matches!(op, Op::JumpIfNonZero(_) | Op::Clear | Op::Scan(_)),
+    };
+}
+
+fn render(ops: &[Op]) -> String {
+    let mut code = String::new();
+
+    let mut i = 0;
+
+    while i < ops.len() {
+        match ops[i] {
+            Op::Add(n) => code.extend(std::iter::repeat_n(if n > 0 { '+' } else { '-' }, n.unsigned_abs() as usize)),
+            Op::Move(n) => code.extend(std::iter::repeat_n(if n > 0 { '>' } else { '<' }, n.unsigned_abs())),
+            Op::JumpIfZero(_) => code.push('['),
+            Op::JumpIfNonZero(_) => code.push(']'),
+            Op::Clear => code.push_str("[-]"),
+            Op::Scan(n) => code.push_str(&render(&[Op::JumpIfZero(0), Op::Move(n), Op::JumpIfNonZero(0)])),
+            Op::MulAdd(..) => {
+                let mut body = vec![Op::JumpIfZero(0), Op::Add(-1)];
+
+                let mut offset = 0;
+
+                while let Op::MulAdd(to, factor) = ops[i] {
+                    body.push(Op::Move(to - offset));
+                    body.push(Op::Add(factor));
+
+                    offset = to;
 
-        if inst == '<' {
-            if ptr == 0 {
-                ptr = MEM_SIZE - 1;
-            } else {
-                ptr -= 1;
+                    i += 1;
+                }
+
+                body.push(Op::Move(-offset));
+                body.push(Op::JumpIfNonZero(0));
+
+                code.push_str(&render(&body));
             }
+            op => code.push(match op {
+                Op::Output => '.',
+                Op::Input => ',',
+                Op::Breakpoint => '#',
+                Op::End => '@',
+                Op::Store => '$',
+                Op::Load => '!',
+                Op::ShiftLeft => '{',
+                Op::ShiftRight => '}',
+                Op::Not => '~',
+                Op::Xor => '^',
+                Op::And => '&',
+                _ => '|',
+            }),
+        }
+
+        i += 1;
+    }
+
+    return code;
+}
+
+// Adjacent inverse operations only cancel when the matching overflow policy wraps, otherwise `+-` can clamp or fail.
+fn simplify(program: &Program, config: &Config) -> Vec<Op> {
+    let modulus = config.cell.max() as i64 + 1;
+
+    let mut ops: Vec<Op> = vec![];
+
+    let mut i = 0;
+
+    while i < program.ops.len() {
+        let op = program.ops[i];
+
+        let starts_loop = matches!(op, Op::JumpIfZero(_) | Op::Clear | Op::Scan(_) | Op::MulAdd(..));
+
+        if starts_loop && ends_loop(ops.last(), &program.data) {
+            i = match op {
+                Op::JumpIfZero(close) => close + 1,
+                Op::MulAdd(..) => i + program.ops[i..].iter().position(|op| *op == Op::Clear).unwrap() + 1,
+                _ => i + 1,
+            };
+
+            continue;
         }
 
-        if inst == '+' {
-            if memory[ptr] == 255 {
-                memory[ptr] = 0;
-            } else {
-                memory[ptr] += 1;
+        match (ops.last().copied(), op) {
+            (Some(Op::Add(a)), Op::Add(b)) if config.cell_overflow == Overflow::Wrap => {
+                ops.pop();
+
+                ops.push(Op::Add(a + b));
+            }
+            (Some(Op::Move(a)), Op::Move(b)) if config.pointer_overflow == Overflow::Wrap && (a.signum() == b.signum() || config.moves_cancel()) => {
+                ops.pop();
+
+                ops.push(Op::Move(a + b));
             }
+            _ => ops.push(op),
         }
 
-        if inst == '-' {
-            if memory[ptr] == 0 {
-                memory[ptr] = 255;
-            } else {
-                memory[ptr] -= 1;
+        match ops.last().copied() {
+            Some(Op::Add(n)) if config.cell_overflow == Overflow::Wrap => {
+                let n = (n as i64).rem_euclid(modulus);
+
+                ops.pop();
+
+                if n != 0 {
+                    ops.push(Op::Add(if n > modulus / 2 { n - modulus } else { n } as i32));
+                }
             }
+            Some(Op::Move(0)) => {
+                ops.pop();
+            }
+            _ => {}
         }
 
-        if inst == '.' {
-            println!("{:>3} {}", memory[ptr], memory[ptr] as char);
+        i += 1;
+    }
+
+    return ops;
+}
+
+// Strips comments and repeats the simplification until nothing changes, since removing code can expose new cancellations.
+fn minify(code: &str, config: &Config) -> Result<String, BrainfuckError> {
+    let (mut minified, data) = match config.instructions {
+        InstructionSet::Extended if code.contains('@') => {
+            let (program, data) = code.split_once('@').unwrap();
+
+            (format!("{}@", program), data)
         }
+        _ => (code.to_string(), ""),
+    };
 
-        if inst == ',' {
+    loop {
+        let next = render(&simplify(&parse(&(minified.clone() + data), config)?, config));
 
+        if next == minified {
+            break;
         }
 
-        if inst == '[' {
-            if memory[ptr] == 0 {
-                i += 1;
+        minified = next;
+    }
+
+    return Ok(minified + data);
+}
+
+fn analyze(code: &str, config: &Config) -> Result<Vec<LoopFact>, BrainfuckError> {
+    let program = parse(code, config)?;
+
+    let chars: Vec<char> = code.chars().collect();
+
+    let mut facts = vec![];
+
+    // Each open loop tracks its net pointer movement, or None once a nested loop makes it data-dependent.
+    let mut opens: Vec<(usize, Option<isize>)> = vec![];
+
+    let mut i = 0;
+
+    while i < program.ops.len() {
+        let kind = match program.ops[i] {
+            Op::Move(n) => {
+                if let Some((_, Some(net))) = opens.last_mut() {
+                    *net += n;
+                }
+
+                None
+            }
+            Op::JumpIfZero(_) => {
+                opens.push((i, Some(0)));
+
+                None
+            }
+            Op::JumpIfNonZero(open) => {
+                let net = opens.pop().unwrap().1;
+
+                let kind = match net {
+                    Some(0) => LoopKind::Balanced,
+                    Some(n) => LoopKind::Unbalanced(n),
+                    None => LoopKind::Unknown,
+                };
 
-                while c > 0 || code.chars().nth(i).unwrap() != ']' {
-                    if code.chars().nth(i).unwrap() == '[' {
-                        c += 1;
-                    } else if code.chars().nth(i).unwrap() == ']' {
-                        c -= 1;
+                if kind != LoopKind::Balanced {
+                    if let Some(parent) = opens.last_mut() {
+                        parent.1 = None;
                     }
+                }
 
-                    i += 1;
+                Some((open, kind))
+            }
+            Op::Clear => Some((i, LoopKind::Clear)),
+            Op::Scan(n) => {
+                if let Some(parent) = opens.last_mut() {
+                    parent.1 = None;
                 }
+
+                Some((i, LoopKind::Scan(n)))
             }
+            Op::MulAdd(..) => {
+                let start = i;
+
+                i += program.ops[i..].iter().position(|op| *op == Op::Clear).unwrap();
+
+                Some((start, LoopKind::Multiply))
+            }
+            _ => None,
+        };
+
+        if let Some((start, kind)) = kind {
+            let dead = ends_loop(if start == 0 { None } else { program.ops.get(start - 1) }, &program.data);
+
+            let (line, column) = line_column(&chars[..program.spans[start].start]);
+
+            facts.push(LoopFact { line, column, kind: if dead { LoopKind::Dead } else { kind } });
         }
 
-        if inst == ']' {
-            if memory[ptr] == 0 {
-                i -= 1;
+        i += 1;
+    }
+
+    facts.sort_by_key(|fact| (fact.line, fact.column));
+
+    return Ok(facts);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Dialect {
+    name: String,
+    instructions: InstructionSet,
+    tokens: Vec<(String, char)>,
+}
+
+impl Dialect {
+    fn new(name: &str, instructions: InstructionSet, tokens: &[(&str, char)]) -> Self {
+        let mut tokens: Vec<(String, char)> = tokens.iter().map(|(t, c)| (t.to_string(), *c)).collect();
+
+        tokens.sort_by_key(|(t, _)| std::cmp::Reverse(t.len()));
+
+        return Dialect { name: name.to_string(), instructions, tokens };
+    }
+
+    fn from_symbols(name: &str, instructions: InstructionSet) -> Self {
+        let tokens = instructions.symbols().chars().map(|c| (c.to_string(), c)).collect();
+
+        return Dialect { name: name.to_string(), instructions, tokens };
+    }
+
+    fn brainfuck() -> Self {
+        return Dialect::from_symbols("brainfuck", InstructionSet::Classic);
+    }
+
+    fn extended() -> Self {
+        return Dialect::from_symbols("extended", InstructionSet::Extended);
+    }
+
+    fn ook() -> Self {
+        return Dialect::new("ook", InstructionSet::Classic, &[
+            ("Ook. Ook?", '>'),
+            ("Ook? Ook.", '<'),
+            ("Ook. Ook.", '+'),
+            ("Ook! Ook!", '-'),
+            ("Ook! Ook.", '.'),
+            ("Ook. Ook!", ','),
+            ("Ook! Ook?", '['),
+            ("Ook? Ook!", ']'),
+        ]);
+    }
+
+    fn alphuck() -> Self {
+        return Dialect::new("alphuck", InstructionSet::Classic, &[
+            ("a", '>'),
+            ("c", '<'),
+            ("e", '+'),
+            ("i", '-'),
+            ("j", '.'),
+            ("o", ','),
+            ("p", '['),
+            ("s", ']'),
+        ]);
+    }
+
+    fn named(name: &str) -> Result<Self, BrainfuckError> {
+        return match name.to_lowercase().as_str() {
+            "brainfuck" | "bf" => Ok(Dialect::brainfuck()),
+            "extended" | "ebf" => Ok(Dialect::extended()),
+            "ook" => Ok(Dialect::ook()),
+            "alphuck" => Ok(Dialect::alphuck()),
+            _ => Err(BrainfuckError::UnknownDialect(name.to_string())),
+        };
+    }
+
+    // Anything that doesn't start a token is a comment, and in Extended Brainfuck whatever follows `@` is data and is
+    // kept as it is.
+    fn to_core(&self, code: &str) -> String {
+        let code: Vec<char> = code.chars().collect();
+
+        let mut core = String::new();
 
-                while c > 0 || code.chars().nth(i).unwrap() != '[' {
-                    if code.chars().nth(i).unwrap() == ']' {
-                        c += 1;
-                    } else if code.chars().nth(i).unwrap() == '[' {
-                        c -= 1;
+        let mut i = 0;
+
+        'outer: while i < code.len() {
+            for (token, inst) in &self.tokens {
+                if let Some(len) = match_token(&code[i..], token) {
+                    core.push(*inst);
+
+                    if *inst == '@' {
+                        core.extend(&code[i + len..]);
+
+                        break 'outer;
                     }
 
-                    i -= 1;
+                    i += len;
+
+                    continue 'outer;
                 }
+            }
+
+            i += 1;
+        }
+
+        return core;
+    }
+
+    fn render_core(&self, core: &str) -> Result<String, BrainfuckError> {
+        let (core, data) = core.split_at(core.find('@').map_or(core.len(), |at| at + 1));
 
-                i -= 1;
+        let mut out = vec![];
+
+        for inst in core.chars() {
+            match self.tokens.iter().find(|(_, c)| *c == inst) {
+                Some((token, _)) => out.push(token.as_str()),
+                None if inst == '#' => {}
+                None => return Err(BrainfuckError::Untranslatable(inst)),
             }
         }
 
-        i += 1;
+        let separator = if self.tokens.iter().any(|(t, _)| t.chars().count() > 1) { " " } else { "" };
+
+        return Ok(out.join(separator) + data);
     }
 }
 
-fn main() {
-    interpret("--[+++++++>-->+>+>+<<<->---.>--..>+.<<<.+>->>.+++[.<]".to_string());
+// Words inside a token may be separated by any run of whitespace, so `Ook.\nOok?` still matches.
+fn match_token(code: &[char], token: &str) -> Option<usize> {
+    let mut i = 0;
+
+    for (k, word) in token.split_whitespace().enumerate() {
+        if k > 0 {
+            let start = i;
+
+            while i < code.len() && code[i].is_whitespace() {
+                i += 1;
+            }
+
+            if i == start {
+                return None;
+            }
+        }
+
+        for c in word.chars() {
+            if code.get(i) != Some(&c) {
+                return None;
+            }
+
+            i += 1;
+        }
+    }
+
+    return Some(i);
+}
+
+fn translate(code: &str, from: &Dialect, to: &Dialect) -> Result<String, BrainfuckError> {
+    return to.render_core(&from.to_core(code));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Budget {
+    steps: Option<u64>,
+    time: Option<Duration>,
+}
+
+impl Budget {
+    fn steps(steps: u64) -> Self {
+        return Budget { steps: Some(steps), time: None };
+    }
+
+    fn time(time: Duration) -> Self {
+        return Budget { steps: None, time: Some(time) };
+    }
+
+    fn unlimited() -> Self {
+        return Budget { steps: None, time: None };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Halted,
+    Suspended,
+}
+
+#[derive(Debug, Clone)]
+struct Profile {
+    counts: Vec<u64>,
+    iterations: Vec<u64>,
+    lowest: usize,
+    highest: usize,
+}
+
+impl Profile {
+    fn new(program: &Program) -> Self {
+        return Profile { counts: vec![0; program.ops.len()], iterations: vec![0; program.ops.len()], lowest: usize::MAX, highest: 0 };
+    }
+
+    fn record(&mut self, program: &Program, pc: usize, machine: &Machine) -> () {
+        self.counts[pc] += 1;
+
+        match program.ops[pc] {
+            Op::JumpIfZero(_) if machine.get() != 0 => self.iterations[pc] += 1,
+            Op::JumpIfNonZero(open) if machine.get() != 0 => self.iterations[open] += 1,
+            Op::Scan(_) if machine.get() != 0 => self.iterations[pc] += 1,
+            Op::MulAdd(offset, _) => self.touch(machine.ptr as isize + offset),
+            // A folded `[-]` or multiply loop runs once per unit in the cell, counted against the first op of the loop.
+            Op::Clear => {
+                let start = pc - program.ops[..pc].iter().rev().take_while(|op| matches!(op, Op::MulAdd(..))).count();
+
+                self.iterations[start] += machine.get() as u64;
+            }
+            _ => {}
+        }
+
+        self.touch(machine.ptr as isize);
+    }
+
+    fn touch(&mut self, cell: isize) -> () {
+        if cell >= 0 {
+            self.lowest = self.lowest.min(cell as usize);
+            self.highest = self.highest.max(cell as usize);
+        }
+    }
+
+    fn annotate(&self, code: &str, program: &Program) -> String {
+        let mut lines: Vec<(String, u64)> = vec![(String::new(), 0)];
+
+        let mut heat = vec![0; code.chars().count()];
+
+        for (pc, span) in program.spans.iter().enumerate() {
+            for cell in &mut heat[span.start..span.end] {
+                *cell = (*cell).max(self.counts[pc]);
+            }
+        }
+
+        for (i, c) in code.chars().enumerate() {
+            if c == '\n' {
+                lines.push((String::new(), 0));
+
+                continue;
+            }
+
+            let line = lines.last_mut().unwrap();
+
+            line.0.push(c);
+            line.1 = line.1.max(heat[i]);
+        }
+
+        let mut out = if self.lowest <= self.highest {
+            format!("{} steps, cells {}..={} touched\n", self.counts.iter().sum::<u64>(), self.lowest, self.highest)
+        } else {
+            "0 steps, no cells touched\n".to_string()
+        };
+
+        for (text, count) in lines {
+            out.push_str(&format!("{:>12} | {}\n", count, text));
+        }
+
+        let mut hottest: Vec<usize> = (0..self.counts.len()).filter(|pc| self.counts[*pc] > 0).collect();
+
+        hottest.sort_by_key(|pc| std::cmp::Reverse(self.counts[*pc]));
+
+        out.push_str("\nhottest instructions\n");
+
+        for pc in hottest.into_iter().take(10) {
+            let span = program.spans[pc];
+
+            let text: String = code.chars().skip(span.start).take(span.end - span.start).collect();
+
+            out.push_str(&format!("{:>12} pc {:<5} {:<20} {}\n", self.counts[pc], pc, format!("{:?}", program.ops[pc]), text));
+        }
+
+        let mut loops: Vec<usize> = (0..self.iterations.len()).filter(|pc| self.iterations[*pc] > 0).collect();
+
+        loops.sort_by_key(|pc| std::cmp::Reverse(self.iterations[*pc]));
+
+        out.push_str("\nhottest loops\n");
+
+        for pc in loops.into_iter().take(10) {
+            let before: Vec<char> = code.chars().take(program.spans[pc].start).collect();
+
+            let (line, column) = line_column(&before);
+
+            out.push_str(&format!("{:>12} iterations of {:?} at {}:{}\n", self.iterations[pc], program.ops[pc], line, column));
+        }
+
+        return out;
+    }
+}
+
+struct Vm {
+    program: Program,
+    machine: Machine,
+    pc: usize,
+    steps: u64,
+    profile: Option<Profile>,
+}
+
+impl Vm {
+    fn new(code: &str, config: Config) -> Result<Self, BrainfuckError> {
+        let program = parse(code, &config)?;
+
+        check_data(&program, &config)?;
+
+        let mut machine = Machine::new(config);
+
+        machine.load(&program.data);
+
+        return Ok(Vm { program, machine, pc: 0, steps: 0, profile: None });
+    }
+
+    fn with_dialect(code: &str, dialect: &Dialect, config: Config) -> Result<Self, BrainfuckError> {
+        return Vm::new(&dialect.to_core(code), Config { instructions: dialect.instructions, ..config });
+    }
+
+    fn enable_profiling(&mut self) -> () {
+        self.profile = Some(Profile::new(&self.program));
+    }
+
+    fn is_halted(&self) -> bool {
+        return self.pc >= self.program.ops.len();
+    }
+
+    fn tick(&mut self) -> Result<(), BrainfuckError> {
+        self.steps += 1;
+
+        return match self.machine.config.step_limit {
+            Some(limit) if self.steps > limit => Err(BrainfuckError::StepLimitExceeded(limit)),
+            _ => Ok(()),
+        };
+    }
+
+    fn step<R: Read, W: Write>(&mut self, input: &mut R, output: &mut W) -> Result<(), BrainfuckError> {
+        self.tick()?;
+
+        if let Some(profile) = self.profile.as_mut() {
+            profile.record(&self.program, self.pc, &self.machine);
+        }
+
+        self.execute(input, output)?;
+
+        // The pointer before each op is recorded with it, this catches where the last op leaves it.
+        if let Some(profile) = self.profile.as_mut() {
+            profile.touch(self.machine.ptr as isize);
+        }
+
+        return Ok(());
+    }
+
+    fn execute<R: Read, W: Write>(&mut self, input: &mut R, output: &mut W) -> Result<(), BrainfuckError> {
+        let machine = &mut self.machine;
+
+        match self.program.ops[self.pc] {
+            Op::Add(n) => machine.add(machine.ptr, n as i64)?,
+            Op::Move(n) => machine.move_by(n)?,
+            Op::Output => write_cell(output, machine.config.output, machine.get())?,
+            Op::Input => {
+                if let Some(value) = read_cell(input, machine.config.eof)? {
+                    machine.set(value);
+                }
+            }
+            Op::JumpIfZero(close) => {
+                if machine.get() == 0 {
+                    self.pc = close;
+                }
+            }
+            Op::JumpIfNonZero(open) => {
+                if machine.get() != 0 {
+                    self.pc = open;
+                }
+            }
+            Op::Clear => machine.set(0),
+            Op::Scan(n) => {
+                if machine.get() != 0 {
+                    machine.move_by(n)?;
+
+                    return Ok(());
+                }
+            }
+            Op::MulAdd(offset, factor) => {
+                let value = machine.get() as i64;
+
+                if value != 0 {
+                    let target = machine.offset(offset)?;
+
+                    let n = match machine.config.cell_overflow {
+                        Overflow::Wrap => value.wrapping_mul(factor as i64),
+                        _ => value.saturating_mul(factor as i64),
+                    };
+
+                    machine.add(target, n)?;
+                }
+            }
+            Op::Breakpoint => {}
+            Op::End => {
+                self.pc = self.program.ops.len();
+
+                return Ok(());
+            }
+            Op::Store => machine.storage = machine.get(),
+            Op::Load => machine.set(machine.storage),
+            Op::ShiftLeft => machine.set(machine.get() << 1),
+            Op::ShiftRight => machine.set(machine.get() >> 1),
+            Op::Not => machine.set(!machine.get()),
+            Op::Xor => machine.set(machine.get() ^ machine.storage),
+            Op::And => machine.set(machine.get() & machine.storage),
+            Op::Or => machine.set(machine.get() | machine.storage),
+        }
+
+        self.pc += 1;
+
+        return Ok(());
+    }
+
+    fn run<R: Read, W: Write>(&mut self, input: &mut R, output: &mut W) -> Result<(), BrainfuckError> {
+        self.run_for(Budget::unlimited(), input, output)?;
+
+        return Ok(());
+    }
+
+    // The clock is only consulted every few thousand steps so timed runs stay cheap.
+    fn run_for<R: Read, W: Write>(&mut self, budget: Budget, input: &mut R, output: &mut W) -> Result<Status, BrainfuckError> {
+        let start = Instant::now();
+
+        let mut steps = 0;
+
+        while !self.is_halted() {
+            if budget.steps.is_some_and(|limit| steps >= limit) {
+                output.flush()?;
+
+                return Ok(Status::Suspended);
+            }
+
+            if steps % 4096 == 0 && budget.time.is_some_and(|limit| start.elapsed() >= limit) {
+                output.flush()?;
+
+                return Ok(Status::Suspended);
+            }
+
+            self.step(input, output)?;
+
+            steps += 1;
+        }
+
+        output.flush()?;
+
+        return Ok(Status::Halted);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    Halted,
+    Breakpoint(usize),
+}
+
+struct Debugger {
+    vm: Vm,
+    source: Vec<char>,
+    breakpoints: Vec<usize>,
+}
+
+impl Debugger {
+    fn new(code: &str, config: Config) -> Result<Self, BrainfuckError> {
+        return Ok(Debugger { vm: Vm::new(code, Config { breakpoints: true, ..config })?, source: code.chars().collect(), breakpoints: vec![] });
+    }
+
+    fn stop(&self) -> Option<Stop> {
+        if self.vm.is_halted() {
+            return Some(Stop::Halted);
+        }
+
+        if self.breakpoints.contains(&self.vm.pc) || self.vm.program.ops[self.vm.pc] == Op::Breakpoint {
+            return Some(Stop::Breakpoint(self.vm.pc));
+        }
+
+        return None;
+    }
+
+    fn step<R: Read, W: Write>(&mut self, input: &mut R, output: &mut W) -> Result<Option<Stop>, BrainfuckError> {
+        if !self.vm.is_halted() {
+            self.vm.step(input, output)?;
+
+            output.flush()?;
+        }
+
+        return Ok(self.stop());
+    }
+
+    fn run_to_breakpoint<R: Read, W: Write>(&mut self, input: &mut R, output: &mut W) -> Result<Stop, BrainfuckError> {
+        loop {
+            if let Some(stop) = self.step(input, output)? {
+                return Ok(stop);
+            }
+        }
+    }
+
+    // Breakpoints are set on source positions and land on the first instruction at or after them.
+    fn add_breakpoint(&mut self, position: usize) -> Option<usize> {
+        let pc = self.vm.program.spans.iter().position(|span| span.end > position)?;
+
+        if !self.breakpoints.contains(&pc) {
+            self.breakpoints.push(pc);
+        }
+
+        return Some(pc);
+    }
+
+    fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        let len = self.breakpoints.len();
+
+        self.breakpoints.retain(|b| *b != pc);
+
+        return self.breakpoints.len() != len;
+    }
+
+    fn tape_window(&self, radius: usize) -> Vec<(usize, u32)> {
+        let tape = &self.vm.machine.tape;
+
+        let start = self.vm.machine.ptr.saturating_sub(radius);
+
+        let end = (self.vm.machine.ptr + radius + 1).min(tape.len());
+
+        return (start..end).map(|i| (i, tape[i])).collect();
+    }
+
+    fn poke(&mut self, cell: usize, value: u32) -> bool {
+        if cell >= self.vm.machine.tape.len() {
+            if self.vm.machine.config.tape != TapeSize::Dynamic {
+                return false;
+            }
+
+            self.vm.machine.tape.resize(cell + 1, 0);
+        }
+
+        self.vm.machine.tape[cell] = value & self.vm.machine.config.cell.max();
+
+        return true;
+    }
+
+    fn location(&self) -> Option<(usize, usize)> {
+        let span = self.vm.program.spans.get(self.vm.pc)?;
+
+        return Some(line_column(&self.source[..span.start]));
+    }
+
+    fn position(&self, line: usize, column: usize) -> Option<usize> {
+        let mut index = 0;
+
+        for (i, text) in self.source.split(|c| *c == '\n').enumerate() {
+            if i + 1 == line {
+                return if column >= 1 && column <= text.len() { Some(index + column - 1) } else { None };
+            }
+
+            index += text.len() + 1;
+        }
+
+        return None;
+    }
+
+    fn describe(&self) -> String {
+        return match (self.location(), self.vm.program.ops.get(self.vm.pc)) {
+            (Some((line, column)), Some(op)) => {
+                let span = self.vm.program.spans[self.vm.pc];
+
+                format!("pc {} at {}:{} {:?} `{}`", self.vm.pc, line, column, op, self.source[span.start..span.end].iter().collect::<String>())
+            }
+            _ => format!("halted after {} steps", self.vm.steps),
+        };
+    }
+}
+
+fn debug_repl<R: Read>(code: &str, config: Config, input: &mut R) -> Result<(), BrainfuckError> {
+    let mut debugger = Debugger::new(code, config)?;
+
+    let mut output = io::stdout();
+
+    let mut line = String::new();
+
+    println!("{}", debugger.describe());
+
+    loop {
+        print!("(bf) ");
+
+        output.flush()?;
+
+        line.clear();
+
+        if io::stdin().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        let args: Vec<&str> = line.split_whitespace().collect();
+
+        let number = |i: usize, default: usize| args.get(i).and_then(|a| a.parse::<usize>().ok()).unwrap_or(default);
+
+        let result = match args.first().copied().unwrap_or("s") {
+            "s" | "step" => {
+                for _ in 0..number(1, 1) {
+                    if debugger.step(input, &mut output)? == Some(Stop::Halted) {
+                        break;
+                    }
+                }
+
+                Ok(())
+            }
+            "c" | "continue" => debugger.run_to_breakpoint(input, &mut output).map(|_| ()),
+            "b" | "break" => {
+                let at = args.get(1).and_then(|a| a.split_once(':')).and_then(|(l, c)| debugger.position(l.parse().ok()?, c.parse().ok()?));
+
+                match at.and_then(|at| debugger.add_breakpoint(at)) {
+                    Some(pc) => println!("breakpoint at pc {}", pc),
+                    None => println!("usage: b <line>:<column>"),
+                }
+
+                Ok(())
+            }
+            "d" | "delete" => {
+                if !debugger.remove_breakpoint(number(1, usize::MAX)) {
+                    println!("no breakpoint at that pc");
+                }
+
+                Ok(())
+            }
+            "t" | "tape" => {
+                for (i, value) in debugger.tape_window(number(1, 4)) {
+                    println!("{} {:>5} {:>10}", if i == debugger.vm.machine.ptr { ">" } else { " " }, i, value);
+                }
+
+                Ok(())
+            }
+            "set" => {
+                let value = args.get(2).and_then(|a| a.parse::<u32>().ok());
+
+                match value {
+                    Some(value) if debugger.poke(number(1, usize::MAX), value) => {}
+                    _ => println!("usage: set <cell> <value>"),
+                }
+
+                Ok(())
+            }
+            "q" | "quit" => return Ok(()),
+            _ => {
+                println!("commands: s [n], c, b <line>:<column>, d <pc>, t [radius], set <cell> <value>, q");
+
+                Ok(())
+            }
+        };
+
+        if let Err(err) = result {
+            println!("{}", err);
+        }
+
+        println!("{}", debugger.describe());
+    }
+}
+
+const C_TEMPLATE: &str = r#"#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef $CELL cell;
+
+#define MAX_CELL $MAX
+static cell *tape;
+static size_t len = $LEN;
+static size_t ptr = 0;
+static cell storage = 0;
+
+static inline size_t offset(long long by) {
+    long long target = (long long)ptr + by;
+
+    if (target >= 0 && target < (long long)len) {
+        return (size_t)target;
+    }
+$POINTER}
+
+static inline void add(size_t at, long long n) {
+$CELL_OVERFLOW}
+
+static inline void mul_add(long long by, long long factor) {
+    if (tape[ptr]) {
+        size_t at = offset(by);
+
+        add(at, $PRODUCT);
+    }
+}
+
+static inline void put_char(uint32_t c) {
+    if ((c >= 0xd800 && c <= 0xdfff) || c > 0x10ffff) {
+        c = 0xfffd;
+    }
+
+    if (c < 0x80) {
+        putchar((int)c);
+    } else if (c < 0x800) {
+        putchar((int)(0xc0 | c >> 6));
+        putchar((int)(0x80 | (c & 0x3f)));
+    } else if (c < 0x10000) {
+        putchar((int)(0xe0 | c >> 12));
+        putchar((int)(0x80 | (c >> 6 & 0x3f)));
+        putchar((int)(0x80 | (c & 0x3f)));
+    } else {
+        putchar((int)(0xf0 | c >> 18));
+        putchar((int)(0x80 | (c >> 12 & 0x3f)));
+        putchar((int)(0x80 | (c >> 6 & 0x3f)));
+        putchar((int)(0x80 | (c & 0x3f)));
+    }
+}
+
+static inline void output(void) {
+$OUTPUT}
+
+static inline void input(void) {
+    int c = getchar();
+
+    if (c != EOF) {
+        tape[ptr] = (cell)c;
+    }$EOF
+}
+
+int main(void) {
+    tape = calloc(len, sizeof(cell));
+
+$BODY
+    return 0;
+}
+"#;
+
+const RUST_TEMPLATE: &str = r#"#![allow(unused)]
+
+use std::io::{self, Read, Write};
+use std::process;
+
+type Cell = $CELL;
+
+const MAX_CELL: i64 = $MAX;
+
+struct Machine {
+    tape: Vec<Cell>,
+    ptr: usize,
+    storage: Cell,
+}
+
+impl Machine {
+    fn offset(&mut self, by: isize) -> usize {
+        let target = self.ptr as isize + by;
+
+        let len = self.tape.len() as isize;
+
+        if target >= 0 && target < len {
+            return target as usize;
+        }
+$POINTER    }
+
+    fn add(&mut self, at: usize, n: i64) {
+$CELL_OVERFLOW    }
+
+    fn mul_add(&mut self, by: isize, factor: i64) {
+        if self.tape[self.ptr] != 0 {
+            let at = self.offset(by);
+
+            self.add(at, $PRODUCT);
+        }
+    }
+
+    fn output<W: Write>(&self, output: &mut W) {
+        let value = self.tape[self.ptr];
+
+$OUTPUT    }
+
+    fn input<R: Read>(&mut self, input: &mut io::Bytes<R>) {
+        match input.next() {
+            Some(byte) => self.tape[self.ptr] = byte.unwrap() as Cell,
+$EOF        }
+    }
+}
+
+fn main() {
+    let mut m = Machine { tape: vec![0; $LEN], ptr: 0, storage: 0 };
+
+    let mut input = io::stdin().lock().bytes();
+
+    let mut output = io::stdout().lock();
+
+$BODY}
+"#;
+
+fn emit_body(program: &Program, emit: impl Fn(&Op) -> Vec<String>) -> String {
+    let mut body = String::new();
+
+    let mut depth = 1;
+
+    for op in &program.ops {
+        if let Op::JumpIfNonZero(_) = op {
+            depth -= 1;
+        }
+
+        for line in emit(op) {
+            body.push_str(&"    ".repeat(depth));
+            body.push_str(&line);
+            body.push('\n');
+        }
+
+        if let Op::JumpIfZero(_) = op {
+            depth += 1;
+        }
+    }
+
+    return body;
+}
+
+fn initial_len(config: &Config) -> usize {
+    return match config.tape {
+        TapeSize::Fixed(len) => len.max(1),
+        TapeSize::Dynamic => 1024,
+    };
+}
+
+// The data section of an Extended Brainfuck program starts out on the tape, so a fixed tape has to hold all of it.
+fn check_data(program: &Program, config: &Config) -> Result<(), BrainfuckError> {
+    return match config.tape {
+        TapeSize::Fixed(len) if program.data.len() > len.max(1) => Err(BrainfuckError::PointerOutOfRange(len.max(1) as isize)),
+        _ => Ok(()),
+    };
+}
+
+// Step limits belong to the interpreter, so the generated programs only honor the machine model.
+fn transpile_c(code: &str, config: &Config) -> Result<String, BrainfuckError> {
+    let program = parse(code, config)?;
+
+    check_data(&program, config)?;
+
+    let cell = match config.cell {
+        CellWidth::U8 => "uint8_t",
+        CellWidth::U16 => "uint16_t",
+        CellWidth::U32 => "uint32_t",
+    };
+
+    let mut pointer = String::new();
+
+    if config.tape == TapeSize::Dynamic {
+        pointer.push_str("\n    if (target >= (long long)len) {\n        size_t grown = (size_t)target + 1 > len * 2 ? (size_t)target + 1 : len * 2;\n\n        tape = realloc(tape, grown * sizeof(cell));\n        memset(tape + len, 0, (grown - len) * sizeof(cell));\n        len = grown;\n\n        return (size_t)target;\n    }\n");
+    }
+
+    pointer.push_str(match config.pointer_overflow {
+        Overflow::Wrap => "\n    return (size_t)(((target % (long long)len) + (long long)len) % (long long)len);\n",
+        Overflow::Saturate => "\n    return target < 0 ? 0 : len - 1;\n",
+        Overflow::Error => "\n    fprintf(stderr, \"pointer out of range: %lld\\n\", target);\n    exit(1);\n",
+    });
+
+    let clamped = "    long long value = (long long)tape[at] + (n > MAX_CELL ? MAX_CELL + 1 : n < -MAX_CELL ? -MAX_CELL - 1 : n);\n\n";
+
+    let cell_overflow = match config.cell_overflow {
+        Overflow::Wrap => "    tape[at] = (cell)((unsigned long long)tape[at] + (unsigned long long)n);\n".to_string(),
+        Overflow::Saturate => format!("{}    tape[at] = (cell)(value < 0 ? 0 : value > MAX_CELL ? MAX_CELL : value);\n", clamped),
+        Overflow::Error => format!("{}    if (value < 0 || value > MAX_CELL) {{\n        fprintf(stderr, \"cell overflow at %lu\\n\", (unsigned long)at);\n        exit(1);\n    }}\n\n    tape[at] = (cell)value;\n", clamped),
+    };
+
+    let product = match config.cell_overflow {
+        Overflow::Wrap => "(long long)((unsigned long long)tape[ptr] * (unsigned long long)factor)",
+        _ => "(long long)tape[ptr] * factor",
+    };
+
+    let eof = match config.eof {
+        EofPolicy::Unchanged => "",
+        EofPolicy::Zero => " else {\n        tape[ptr] = 0;\n    }",
+        EofPolicy::Max => " else {\n        tape[ptr] = (cell)MAX_CELL;\n    }",
+    };
+
+    let output = match config.output {
+        OutputFormat::Raw => "    putchar((int)(tape[ptr] & 0xff));\n",
+        OutputFormat::Decimal => "    printf(\"%3lu \", (unsigned long)tape[ptr]);\n    put_char((uint32_t)tape[ptr]);\n    putchar('\\n');\n",
+    };
+
+    let data: String = program.data.iter().enumerate().map(|(i, byte)| format!("    tape[{}] = {};\n", i, byte)).collect();
+
+    let body = data + &emit_body(&program, |op| match *op {
+        Op::Add(n) => vec![format!("add(ptr, {}LL);", n)],
+        Op::Move(n) => vec![format!("ptr = offset({}LL);", n)],
+        Op::Output => vec!["output();".to_string()],
+        Op::Input => vec!["input();".to_string()],
+        Op::JumpIfZero(_) => vec!["while (tape[ptr]) {".to_string()],
+        Op::JumpIfNonZero(_) => vec!["}".to_string()],
+        Op::Clear => vec!["tape[ptr] = 0;".to_string()],
+        Op::Scan(n) => vec!["while (tape[ptr]) {".to_string(), format!("    ptr = offset({}LL);", n), "}".to_string()],
+        Op::MulAdd(offset, factor) => vec![format!("mul_add({}LL, {}LL);", offset, factor)],
+        Op::Breakpoint => vec![],
+        Op::End => vec!["return 0;".to_string()],
+        Op::Store => vec!["storage = tape[ptr];".to_string()],
+        Op::Load => vec!["tape[ptr] = storage;".to_string()],
+        Op::ShiftLeft => vec!["tape[ptr] = (cell)(tape[ptr] << 1);".to_string()],
+        Op::ShiftRight => vec!["tape[ptr] = (cell)(tape[ptr] >> 1);".to_string()],
+        Op::Not => vec!["tape[ptr] = (cell)~tape[ptr];".to_string()],
+        Op::Xor => vec!["tape[ptr] ^= storage;".to_string()],
+        Op::And => vec!["tape[ptr] &= storage;".to_string()],
+        Op::Or => vec!["tape[ptr] |= storage;".to_string()],
+    });
+
+    return Ok(C_TEMPLATE
+        .replace("$CELL_OVERFLOW", &cell_overflow)
+        .replace("$CELL", cell)
+        .replace("$MAX", &format!("{}LL", config.cell.max()))
+        .replace("$LEN", &initial_len(config).max(program.data.len()).to_string())
+        .replace("$POINTER", &pointer)
+        .replace("$PRODUCT", product)
+        .replace("$EOF", eof)
+        .replace("$OUTPUT", output)
+        .replace("$BODY", &body));
+}
+
+fn transpile_rust(code: &str, config: &Config) -> Result<String, BrainfuckError> {
+    let program = parse(code, config)?;
+
+    check_data(&program, config)?;
+
+    let cell = match config.cell {
+        CellWidth::U8 => "u8",
+        CellWidth::U16 => "u16",
+        CellWidth::U32 => "u32",
+    };
+
+    let mut pointer = String::new();
+
+    if config.tape == TapeSize::Dynamic {
+        pointer.push_str("\n        if target >= len {\n            self.tape.resize((target as usize + 1).max(self.tape.len() * 2), 0);\n\n            return target as usize;\n        }\n");
+    }
+
+    pointer.push_str(match config.pointer_overflow {
+        Overflow::Wrap => "\n        return target.rem_euclid(len) as usize;\n",
+        Overflow::Saturate => "\n        return target.clamp(0, len - 1) as usize;\n",
+        Overflow::Error => "\n        eprintln!(\"pointer out of range: {}\", target);\n\n        process::exit(1);\n",
+    });
+
+    let cell_overflow = match config.cell_overflow {
+        Overflow::Wrap => "        self.tape[at] = (self.tape[at] as i64).wrapping_add(n) as Cell;\n",
+        Overflow::Saturate => "        self.tape[at] = (self.tape[at] as i64).saturating_add(n).clamp(0, MAX_CELL) as Cell;\n",
+        Overflow::Error => "        match (self.tape[at] as i64).checked_add(n) {\n            Some(value) if value >= 0 && value <= MAX_CELL => self.tape[at] = value as Cell,\n            _ => {\n                eprintln!(\"cell overflow at {}\", at);\n\n                process::exit(1);\n            }\n        }\n",
+    };
+
+    let product = match config.cell_overflow {
+        Overflow::Wrap => "(self.tape[self.ptr] as i64).wrapping_mul(factor)",
+        _ => "(self.tape[self.ptr] as i64).saturating_mul(factor)",
+    };
+
+    let eof = match config.eof {
+        EofPolicy::Unchanged => "            None => {}\n",
+        EofPolicy::Zero => "            None => self.tape[self.ptr] = 0,\n",
+        EofPolicy::Max => "            None => self.tape[self.ptr] = MAX_CELL as Cell,\n",
+    };
+
+    let output = match config.output {
+        OutputFormat::Raw => "        output.write_all(&[value as u8]).unwrap();\n",
+        OutputFormat::Decimal => "        writeln!(output, \"{:>3} {}\", value, char::from_u32(value as u32).unwrap_or(char::REPLACEMENT_CHARACTER)).unwrap();\n",
+    };
+
+    let data: String = program.data.iter().enumerate().map(|(i, byte)| format!("    m.tape[{}] = {};\n", i, byte)).collect();
+
+    let body = data + &emit_body(&program, |op| match *op {
+        Op::Add(n) => vec![format!("m.add(m.ptr, {});", n)],
+        Op::Move(n) => vec![format!("m.ptr = m.offset({});", n)],
+        Op::Output => vec!["m.output(&mut output);".to_string()],
+        Op::Input => vec!["m.input(&mut input);".to_string()],
+        Op::JumpIfZero(_) => vec!["while m.tape[m.ptr] != 0 {".to_string()],
+        Op::JumpIfNonZero(_) => vec!["}".to_string()],
+        Op::Clear => vec!["m.tape[m.ptr] = 0;".to_string()],
+        Op::Scan(n) => vec!["while m.tape[m.ptr] != 0 {".to_string(), format!("    m.ptr = m.offset({});", n), "}".to_string()],
+        Op::MulAdd(offset, factor) => vec![format!("m.mul_add({}, {});", offset, factor)],
+        Op::Breakpoint => vec![],
+        Op::End => vec!["return;".to_string()],
+        Op::Store => vec!["m.storage = m.tape[m.ptr];".to_string()],
+        Op::Load => vec!["m.tape[m.ptr] = m.storage;".to_string()],
+        Op::ShiftLeft => vec!["m.tape[m.ptr] <<= 1;".to_string()],
+        Op::ShiftRight => vec!["m.tape[m.ptr] >>= 1;".to_string()],
+        Op::Not => vec!["m.tape[m.ptr] = !m.tape[m.ptr];".to_string()],
+        Op::Xor => vec!["m.tape[m.ptr] ^= m.storage;".to_string()],
+        Op::And => vec!["m.tape[m.ptr] &= m.storage;".to_string()],
+        Op::Or => vec!["m.tape[m.ptr] |= m.storage;".to_string()],
+    });
+
+    return Ok(RUST_TEMPLATE
+        .replace("$CELL_OVERFLOW", cell_overflow)
+        .replace("$CELL", cell)
+        .replace("$MAX", &config.cell.max().to_string())
+        .replace("$LEN", &initial_len(config).max(program.data.len()).to_string())
+        .replace("$POINTER", &pointer)
+        .replace("$PRODUCT", product)
+        .replace("$EOF", eof)
+        .replace("$OUTPUT", output)
+        .replace("$BODY", &body));
+}
+
+// A tiny structured language that compiles down to Brainfuck for the default 8-bit wrapping machine.
+mod lang {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Token {
+        Number(u32),
+        Name(String),
+        Text(String),
+        Symbol(&'static str),
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum BinOp {
+        Add,
+        Sub,
+        Mul,
+        Eq,
+        Ne,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Expr {
+        Number(u32),
+        Var(String, usize),
+        Not(Box<Expr>),
+        Binary(BinOp, Box<Expr>, Box<Expr>),
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum Stmt {
+        Assign(String, Expr),
+        Print(Expr),
+        PrintText(String),
+        While(Expr, Vec<Stmt>),
+        If(Expr, Vec<Stmt>, Vec<Stmt>),
+    }
+
+    const SYMBOLS: [&str; 12] = ["==", "!=", "=", ";", "{", "}", "(", ")", "+", "-", "*", "!"];
+
+    fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, BrainfuckError> {
+        let chars: Vec<char> = source.chars().collect();
+
+        let mut tokens = vec![];
+
+        let mut line = 1;
+
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+
+            if c == '\n' {
+                line += 1;
+            }
+
+            if c.is_whitespace() {
+                i += 1;
+
+                continue;
+            }
+
+            if chars[i..].starts_with(&['/', '/']) {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+
+                continue;
+            }
+
+            let start = i;
+
+            if c.is_ascii_digit() {
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+
+                let text: String = chars[start..i].iter().collect();
+
+                tokens.push((Token::Number(text.parse().map_err(|_| BrainfuckError::Syntax(line, format!("number too large: {}", text)))?), line));
+
+                continue;
+            }
+
+            if c.is_alphabetic() || c == '_' {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+
+                tokens.push((Token::Name(chars[start..i].iter().collect()), line));
+
+                continue;
+            }
+
+            if c == '"' || c == '\'' {
+                let mut text = String::new();
+
+                i += 1;
+
+                while i < chars.len() && chars[i] != c {
+                    text.push(match (chars[i], chars.get(i + 1)) {
+                        ('\\', Some('n')) => '\n',
+                        ('\\', Some(escaped)) => *escaped,
+                        (plain, _) => plain,
+                    });
+
+                    i += if chars[i] == '\\' { 2 } else { 1 };
+                }
+
+                if i >= chars.len() {
+                    return Err(BrainfuckError::Syntax(line, "unterminated literal".to_string()));
+                }
+
+                i += 1;
+
+                // Character literals are just numbers.
+                tokens.push((match (c, text.chars().collect::<Vec<char>>().as_slice()) {
+                    ('"', _) => Token::Text(text),
+                    (_, [single]) => Token::Number(*single as u32),
+                    _ => return Err(BrainfuckError::Syntax(line, format!("invalid character literal '{}'", text))),
+                }, line));
+
+                continue;
+            }
+
+            match SYMBOLS.iter().find(|symbol| chars[i..].starts_with(&symbol.chars().collect::<Vec<char>>())) {
+                Some(symbol) => {
+                    tokens.push((Token::Symbol(symbol), line));
+
+                    i += symbol.len();
+                }
+                None => return Err(BrainfuckError::Syntax(line, format!("unexpected character '{}'", c))),
+            }
+        }
+
+        return Ok(tokens);
+    }
+
+    struct Parser {
+        tokens: Vec<(Token, usize)>,
+        pos: usize,
+    }
+
+    impl Parser {
+        fn line(&self) -> usize {
+            return self.tokens.get(self.pos).or(self.tokens.last()).map_or(1, |(_, line)| *line);
+        }
+
+        fn error(&self, message: String) -> BrainfuckError {
+            return BrainfuckError::Syntax(self.line(), message);
+        }
+
+        fn peek(&self) -> Option<&Token> {
+            return self.tokens.get(self.pos).map(|(token, _)| token);
+        }
+
+        fn next(&mut self) -> Result<Token, BrainfuckError> {
+            let token = self.peek().cloned().ok_or_else(|| self.error("unexpected end of input".to_string()))?;
+
+            self.pos += 1;
+
+            return Ok(token);
+        }
+
+        fn eat(&mut self, symbol: &str) -> bool {
+            if self.peek() == Some(&Token::Symbol(SYMBOLS.iter().find(|s| **s == symbol).unwrap())) {
+                self.pos += 1;
+
+                return true;
+            }
+
+            return false;
+        }
+
+        fn expect(&mut self, symbol: &str) -> Result<(), BrainfuckError> {
+            if !self.eat(symbol) {
+                return Err(self.error(format!("expected '{}'", symbol)));
+            }
+
+            return Ok(());
+        }
+
+        fn block(&mut self) -> Result<Vec<Stmt>, BrainfuckError> {
+            self.expect("{")?;
+
+            let mut body = vec![];
+
+            while !self.eat("}") {
+                body.push(self.statement()?);
+            }
+
+            return Ok(body);
+        }
+
+        fn statement(&mut self) -> Result<Stmt, BrainfuckError> {
+            let stmt = match self.next()? {
+                Token::Name(name) if name == "while" => return Ok(Stmt::While(self.expr()?, self.block()?)),
+                Token::Name(name) if name == "if" => {
+                    let cond = self.expr()?;
+
+                    let then = self.block()?;
+
+                    let otherwise = if self.peek() == Some(&Token::Name("else".to_string())) {
+                        self.pos += 1;
+
+                        self.block()?
+                    } else {
+                        vec![]
+                    };
+
+                    return Ok(Stmt::If(cond, then, otherwise));
+                }
+                Token::Name(name) if name == "print" => match self.peek() {
+                    Some(Token::Text(text)) => {
+                        let text = text.clone();
+
+                        self.pos += 1;
+
+                        Stmt::PrintText(text)
+                    }
+                    _ => Stmt::Print(self.expr()?),
+                },
+                Token::Name(name) => {
+                    self.expect("=")?;
+
+                    Stmt::Assign(name, self.expr()?)
+                }
+                token => {
+                    self.pos -= 1;
+
+                    return Err(self.error(format!("expected a statement, found {:?}", token)));
+                }
+            };
+
+            self.expect(";")?;
+
+            return Ok(stmt);
+        }
+
+        fn expr(&mut self) -> Result<Expr, BrainfuckError> {
+            let mut left = self.additive()?;
+
+            loop {
+                let op = if self.eat("==") {
+                    BinOp::Eq
+                } else if self.eat("!=") {
+                    BinOp::Ne
+                } else {
+                    return Ok(left);
+                };
+
+                left = Expr::Binary(op, Box::new(left), Box::new(self.additive()?));
+            }
+        }
+
+        fn additive(&mut self) -> Result<Expr, BrainfuckError> {
+            let mut left = self.term()?;
+
+            loop {
+                let op = if self.eat("+") {
+                    BinOp::Add
+                } else if self.eat("-") {
+                    BinOp::Sub
+                } else {
+                    return Ok(left);
+                };
+
+                left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+            }
+        }
+
+        fn term(&mut self) -> Result<Expr, BrainfuckError> {
+            let mut left = self.unary()?;
+
+            while self.eat("*") {
+                left = Expr::Binary(BinOp::Mul, Box::new(left), Box::new(self.unary()?));
+            }
+
+            return Ok(left);
+        }
+
+        fn unary(&mut self) -> Result<Expr, BrainfuckError> {
+            if self.eat("!") {
+                return Ok(Expr::Not(Box::new(self.unary()?)));
+            }
+
+            if self.eat("(") {
+                let inner = self.expr()?;
+
+                self.expect(")")?;
+
+                return Ok(inner);
+            }
+
+            let line = self.line();
+
+            return match self.next()? {
+                Token::Number(n) if n > 255 => Err(BrainfuckError::Syntax(line, format!("number out of range: {}", n))),
+                Token::Number(n) => Ok(Expr::Number(n)),
+                Token::Name(name) => Ok(Expr::Var(name, line)),
+                token => {
+                    self.pos -= 1;
+
+                    Err(self.error(format!("expected an expression, found {:?}", token)))
+                }
+            };
+        }
+    }
+
+    fn assigned(body: &[Stmt], names: &mut Vec<String>) -> () {
+        for stmt in body {
+            match stmt {
+                Stmt::Assign(name, _) if !names.contains(name) => names.push(name.clone()),
+                Stmt::While(_, body) => assigned(body, names),
+                Stmt::If(_, then, otherwise) => {
+                    assigned(then, names);
+                    assigned(otherwise, names);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Variables get their cells before any code is generated, so a loop body can't hand a variable's cell out as a temporary
+    // on an earlier line. Temporaries are handed out first-fit and must be zero when freed. `defined` follows the source, so
+    // a variable can only be read after its first assignment.
+    struct Generator {
+        code: String,
+        ptr: usize,
+        used: Vec<bool>,
+        vars: Vec<(String, usize)>,
+        defined: Vec<String>,
+    }
+
+    impl Generator {
+        fn at(&mut self, cell: usize) -> () {
+            let symbol = if cell > self.ptr { '>' } else { '<' };
+
+            self.code.extend(std::iter::repeat_n(symbol, cell.abs_diff(self.ptr)));
+
+            self.ptr = cell;
+        }
+
+        fn emit(&mut self, cell: usize, code: &str) -> () {
+            self.at(cell);
+
+            self.code.push_str(code);
+        }
+
+        fn add(&mut self, cell: usize, n: u8) -> () {
+            let code = if n < 128 { "+".repeat(n as usize) } else { "-".repeat(256 - n as usize) };
+
+            self.emit(cell, &code);
+        }
+
+        fn alloc(&mut self) -> usize {
+            let cell = self.used.iter().position(|used| !used).unwrap_or(self.used.len());
+
+            if cell == self.used.len() {
+                self.used.push(false);
+            }
+
+            self.used[cell] = true;
+
+            return cell;
+        }
+
+        fn free(&mut self, cell: usize) -> () {
+            self.emit(cell, "[-]");
+
+            self.used[cell] = false;
+        }
+
+        // Empties `from` into each target, adding or subtracting one per unit.
+        fn drain(&mut self, from: usize, targets: &[(usize, &str)]) -> () {
+            self.emit(from, "[-");
+
+            for (target, code) in targets {
+                self.emit(*target, code);
+            }
+
+            self.emit(from, "]");
+        }
+
+        fn copy(&mut self, from: usize) -> usize {
+            let copy = self.alloc();
+
+            let spare = self.alloc();
+
+            self.drain(from, &[(copy, "+"), (spare, "+")]);
+            self.drain(spare, &[(from, "+")]);
+
+            self.free(spare);
+
+            return copy;
+        }
+
+        fn not(&mut self, cell: usize) -> usize {
+            let result = self.alloc();
+
+            self.emit(result, "+");
+            self.emit(cell, "[");
+            self.emit(result, "-");
+            self.emit(cell, "[-]]");
+
+            self.free(cell);
+
+            return result;
+        }
+
+        fn expr(&mut self, expr: &Expr) -> Result<usize, BrainfuckError> {
+            return match expr {
+                Expr::Number(n) => {
+                    let cell = self.alloc();
+
+                    self.add(cell, *n as u8);
+
+                    Ok(cell)
+                }
+                Expr::Var(name, line) => match self.vars.iter().find(|(var, _)| var == name) {
+                    Some(&(_, cell)) if self.defined.contains(name) => Ok(self.copy(cell)),
+                    Some(_) => Err(BrainfuckError::Syntax(*line, format!("variable '{}' is read before it is assigned", name))),
+                    None => Err(BrainfuckError::Syntax(*line, format!("undefined variable '{}'", name))),
+                },
+                Expr::Not(inner) => {
+                    let cell = self.expr(inner)?;
+
+                    Ok(self.not(cell))
+                }
+                Expr::Binary(BinOp::Mul, left, right) => {
+                    let left = self.expr(left)?;
+
+                    let right = self.expr(right)?;
+
+                    let product = self.alloc();
+
+                    self.emit(left, "[-");
+
+                    let addend = self.copy(right);
+
+                    self.drain(addend, &[(product, "+")]);
+
+                    self.free(addend);
+
+                    self.emit(left, "]");
+
+                    self.free(right);
+                    self.free(left);
+
+                    Ok(product)
+                }
+                Expr::Binary(op, left, right) => {
+                    let left = self.expr(left)?;
+
+                    let right = self.expr(right)?;
+
+                    self.drain(right, &[(left, if *op == BinOp::Add { "+" } else { "-" })]);
+
+                    self.free(right);
+
+                    Ok(match op {
+                        BinOp::Eq => self.not(left),
+                        BinOp::Ne => {
+                            let different = self.not(left);
+
+                            self.not(different)
+                        }
+                        _ => left,
+                    })
+                }
+            };
+        }
+
+        fn block(&mut self, body: &[Stmt]) -> Result<(), BrainfuckError> {
+            for stmt in body {
+                self.statement(stmt)?;
+            }
+
+            return Ok(());
+        }
+
+        fn statement(&mut self, stmt: &Stmt) -> Result<(), BrainfuckError> {
+            match stmt {
+                Stmt::Assign(name, value) => {
+                    let value = self.expr(value)?;
+
+                    let var = self.vars.iter().find(|(var, _)| var == name).unwrap().1;
+
+                    self.emit(var, "[-]");
+
+                    self.drain(value, &[(var, "+")]);
+
+                    self.free(value);
+
+                    if !self.defined.contains(name) {
+                        self.defined.push(name.clone());
+                    }
+                }
+                Stmt::Print(value) => {
+                    let value = self.expr(value)?;
+
+                    self.emit(value, ".");
+
+                    self.free(value);
+                }
+                Stmt::PrintText(text) => {
+                    let cell = self.alloc();
+
+                    let mut current = 0u8;
+
+                    for byte in text.bytes() {
+                        self.add(cell, byte.wrapping_sub(current));
+                        self.emit(cell, ".");
+
+                        current = byte;
+                    }
+
+                    self.free(cell);
+                }
+                Stmt::While(cond, body) => {
+                    let flag = self.expr(cond)?;
+
+                    self.emit(flag, "[");
+
+                    self.block(body)?;
+
+                    self.emit(flag, "[-]");
+
+                    let again = self.expr(cond)?;
+
+                    self.drain(again, &[(flag, "+")]);
+
+                    self.free(again);
+
+                    self.emit(flag, "]");
+
+                    self.free(flag);
+                }
+                Stmt::If(cond, then, otherwise) => {
+                    let flag = self.expr(cond)?;
+
+                    let orelse = self.alloc();
+
+                    self.emit(orelse, "+");
+                    self.emit(flag, "[");
+
+                    self.block(then)?;
+
+                    self.emit(orelse, "-");
+                    self.emit(flag, "[-]]");
+                    self.emit(orelse, "[");
+
+                    self.block(otherwise)?;
+
+                    self.emit(orelse, "-]");
+
+                    self.free(orelse);
+                    self.free(flag);
+                }
+            }
+
+            return Ok(());
+        }
+    }
+
+    pub fn compile(source: &str) -> Result<String, BrainfuckError> {
+        let mut parser = Parser { tokens: tokenize(source)?, pos: 0 };
+
+        let mut program = vec![];
+
+        while parser.peek().is_some() {
+            program.push(parser.statement()?);
+        }
+
+        let mut names = vec![];
+
+        assigned(&program, &mut names);
+
+        let vars = names.into_iter().enumerate().map(|(cell, name)| (name, cell)).collect::<Vec<(String, usize)>>();
+
+        let mut generator = Generator { code: String::new(), ptr: 0, used: vec![true; vars.len()], vars, defined: vec![] };
+
+        generator.block(&program)?;
+
+        return minify(&generator.code, &Config::default());
+    }
+}
+
+fn write_cell<W: Write>(output: &mut W, format: OutputFormat, value: u32) -> io::Result<()> {
+    return match format {
+        OutputFormat::Raw => output.write_all(&[value as u8]),
+        OutputFormat::Decimal => writeln!(output, "{:>3} {}", value, char::from_u32(value).unwrap_or(char::REPLACEMENT_CHARACTER)),
+    };
+}
+
+// `None` means the cell is left as it is.
+fn read_cell<R: Read>(input: &mut R, eof: EofPolicy) -> io::Result<Option<u32>> {
+    let mut byte = [0u8; 1];
+
+    return match input.read_exact(&mut byte) {
+        Ok(()) => Ok(Some(byte[0] as u32)),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => match eof {
+            EofPolicy::Unchanged => Ok(None),
+            EofPolicy::Zero => Ok(Some(0)),
+            EofPolicy::Max => Ok(Some(u32::MAX)),
+        },
+        Err(err) => Err(err),
+    };
+}
+
+// The JIT only covers the wrap-everywhere machine on a fixed tape; anything stricter goes through the VM.
+fn supports_jit(config: &Config) -> bool {
+    return cfg!(all(target_os = "linux", target_arch = "x86_64"))
+        && config.cell_overflow == Overflow::Wrap
+        && config.pointer_overflow == Overflow::Wrap
+        && config.step_limit.is_none()
+        && matches!(config.tape, TapeSize::Fixed(len) if len <= i32::MAX as usize);
+}
+
+fn interpret_jit<R: Read, W: Write>(code: &str, config: Config, input: &mut R, output: &mut W) -> Result<(), BrainfuckError> {
+    if !supports_jit(&config) {
+        return interpret(code, config, input, output);
+    }
+
+    return jit::run(&parse(code, &config)?, config, input, output);
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod jit {
+    use super::*;
+    use std::ffi::c_void;
+
+    extern "C" {
+        fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut c_void;
+        fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+        fn munmap(addr: *mut c_void, len: usize) -> i32;
+    }
+
+    const PROT_READ: i32 = 1;
+    const PROT_WRITE: i32 = 2;
+    const PROT_EXEC: i32 = 4;
+    const MAP_PRIVATE: i32 = 2;
+    const MAP_ANONYMOUS: i32 = 0x20;
+
+    const RBX: u8 = 3;
+    const RCX: u8 = 1;
+
+    // The generated code reads and writes the first three fields, so their layout is fixed.
+    #[repr(C)]
+    struct Context<'a, R: Read, W: Write> {
+        tape: *mut u8,
+        ptr: u64,
+        storage: u64,
+        input: &'a mut R,
+        output: &'a mut W,
+        config: Config,
+        error: Option<io::Error>,
+    }
+
+    extern "C" fn output<R: Read, W: Write>(ctx: *mut Context<R, W>, value: u32) -> i32 {
+        let ctx = unsafe { &mut *ctx };
+
+        return match write_cell(ctx.output, ctx.config.output, value) {
+            Ok(()) => 0,
+            Err(err) => {
+                ctx.error = Some(err);
+
+                1
+            }
+        };
+    }
+
+    extern "C" fn input<R: Read, W: Write>(ctx: *mut Context<R, W>) -> i64 {
+        let ctx = unsafe { &mut *ctx };
+
+        return match read_cell(ctx.input, ctx.config.eof) {
+            Ok(Some(value)) => value as i64,
+            Ok(None) => -1,
+            Err(err) => {
+                ctx.error = Some(err);
+
+                -2
+            }
+        };
+    }
+
+    struct Assembler {
+        code: Vec<u8>,
+        width: CellWidth,
+        len: usize,
+    }
+
+    impl Assembler {
+        fn emit(&mut self, bytes: &[u8]) -> () {
+            self.code.extend_from_slice(bytes);
+        }
+
+        fn imm32(&mut self, value: u32) -> () {
+            self.emit(&value.to_le_bytes());
+        }
+
+        // Emits `opcode [r12 + index * width]` with `reg` in the ModRM reg field.
+        fn cell(&mut self, opcodes: [&[u8]; 3], reg: u8, index: u8) -> () {
+            let (prefix, opcode, scale): (&[u8], &[u8], u8) = match self.width {
+                CellWidth::U8 => (&[], opcodes[0], 0),
+                CellWidth::U16 => (&[0x66], opcodes[1], 1),
+                CellWidth::U32 => (&[], opcodes[2], 2),
+            };
+
+            self.emit(prefix);
+            self.emit(&[0x41]);
+            self.emit(opcode);
+            self.emit(&[reg << 3 | 0x04, scale << 6 | index << 3 | 0x04]);
+        }
+
+        fn cell_imm(&mut self, value: u32) -> () {
+            match self.width {
+                CellWidth::U8 => self.emit(&[value as u8]),
+                CellWidth::U16 => self.emit(&(value as u16).to_le_bytes()),
+                CellWidth::U32 => self.imm32(value),
+            }
+        }
+
+        fn add_cell(&mut self, n: i32) -> () {
+            self.cell([&[0x80], &[0x81], &[0x81]], 0, RBX);
+            self.cell_imm(n as u32);
+        }
+
+        fn clear_cell(&mut self) -> () {
+            self.cell([&[0xc6], &[0xc7], &[0xc7]], 0, RBX);
+            self.cell_imm(0);
+        }
+
+        fn cmp_cell_zero(&mut self) -> () {
+            self.cell([&[0x80], &[0x83], &[0x83]], 7, RBX);
+            self.emit(&[0x00]);
+        }
+
+        // movzx must not take the operand-size prefix, or only the low half of eax gets written.
+        fn load_cell(&mut self) -> () {
+            if self.width == CellWidth::U16 {
+                self.emit(&[0x41, 0x0f, 0xb7, 0x04, 0x5c]);
+
+                return;
+            }
+
+            self.cell([&[0x0f, 0xb6], &[], &[0x8b]], 0, RBX);
+        }
+
+        fn store_cell(&mut self) -> () {
+            self.cell([&[0x88], &[0x89], &[0x89]], 0, RBX);
+        }
+
+        // Moves are reduced modulo the tape length up front, so a single subtraction wraps them.
+        fn wrap_index(&mut self, reg: u8, by: isize) -> () {
+            let by = by.rem_euclid(self.len as isize) as u32;
+
+            if by == 0 {
+                return;
+            }
+
+            self.emit(&[0x48, 0x81, 0xc0 | reg]);
+            self.imm32(by);
+            self.emit(&[0x48, 0x81, 0xf8 | reg]);
+            self.imm32(self.len as u32);
+            self.emit(&[0x72, 0x07, 0x48, 0x81, 0xe8 | reg]);
+            self.imm32(self.len as u32);
+        }
+
+        fn jump(&mut self, opcode: &[u8]) -> usize {
+            self.emit(opcode);
+            self.imm32(0);
+
+            return self.code.len() - 4;
+        }
+
+        fn patch(&mut self, at: usize, target: usize) -> () {
+            let rel = target as i64 - (at as i64 + 4);
+
+            self.code[at..at + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+        }
+
+        fn call(&mut self, function: usize) -> () {
+            self.emit(&[0x4c, 0x89, 0xef, 0x48, 0xb8]);
+            self.emit(&(function as u64).to_le_bytes());
+            self.emit(&[0xff, 0xd0]);
+        }
+    }
+
+    fn compile<R: Read, W: Write>(program: &Program, config: &Config) -> Vec<u8> {
+        let len = match config.tape {
+            TapeSize::Fixed(len) => len.max(1),
+            TapeSize::Dynamic => unreachable!(),
+        };
+
+        let mut asm = Assembler { code: vec![], width: config.cell, len };
+
+        asm.emit(&[0x53, 0x41, 0x54, 0x41, 0x55, 0x41, 0x56, 0x41, 0x57]);
+        asm.emit(&[0x49, 0x89, 0xfd, 0x4d, 0x8b, 0x65, 0x00, 0x49, 0x8b, 0x5d, 0x08, 0x4d, 0x8b, 0x75, 0x10]);
+
+        let mut starts = vec![];
+
+        let mut jumps = vec![];
+
+        let mut exits = vec![];
+
+        let mut failures = vec![];
+
+        for op in &program.ops {
+            starts.push(asm.code.len());
+
+            match *op {
+                Op::Add(n) => asm.add_cell(n),
+                Op::Move(n) => asm.wrap_index(RBX, n),
+                Op::Output => {
+                    asm.load_cell();
+                    asm.emit(&[0x89, 0xc6]);
+                    asm.call(output::<R, W> as *const () as usize);
+                    asm.emit(&[0x85, 0xc0]);
+                    failures.push(asm.jump(&[0x0f, 0x85]));
+                }
+                Op::Input => {
+                    asm.call(input::<R, W> as *const () as usize);
+                    asm.emit(&[0x48, 0x83, 0xf8, 0xfe]);
+                    failures.push(asm.jump(&[0x0f, 0x84]));
+                    asm.emit(&[0x48, 0x83, 0xf8, 0xff]);
+                    asm.emit(&[0x74, if config.cell == CellWidth::U16 { 5 } else { 4 }]);
+                    asm.store_cell();
+                }
+                Op::JumpIfZero(close) => {
+                    asm.cmp_cell_zero();
+                    jumps.push((asm.jump(&[0x0f, 0x84]), close + 1));
+                }
+                Op::JumpIfNonZero(open) => {
+                    asm.cmp_cell_zero();
+                    jumps.push((asm.jump(&[0x0f, 0x85]), open + 1));
+                }
+                Op::Clear => asm.clear_cell(),
+                Op::Scan(n) => {
+                    let top = asm.code.len();
+
+                    asm.cmp_cell_zero();
+
+                    let done = asm.jump(&[0x0f, 0x84]);
+
+                    asm.wrap_index(RBX, n);
+
+                    let back = asm.jump(&[0xe9]);
+
+                    asm.patch(back, top);
+
+                    let end = asm.code.len();
+
+                    asm.patch(done, end);
+                }
+                Op::MulAdd(offset, factor) => {
+                    asm.load_cell();
+                    asm.emit(&[0x69, 0xc0]);
+                    asm.imm32(factor as u32);
+                    asm.emit(&[0x48, 0x89, 0xd9]);
+                    asm.wrap_index(RCX, offset);
+                    asm.cell([&[0x00], &[0x01], &[0x01]], 0, RCX);
+                }
+                Op::Breakpoint => {}
+                Op::End => exits.push(asm.jump(&[0xe9])),
+                Op::Store => {
+                    asm.load_cell();
+                    asm.emit(&[0x41, 0x89, 0xc6]);
+                }
+                Op::Load => {
+                    asm.emit(&[0x44, 0x89, 0xf0]);
+                    asm.store_cell();
+                }
+                Op::ShiftLeft | Op::ShiftRight | Op::Not | Op::Xor | Op::And | Op::Or => {
+                    let bytes: &[u8] = match *op {
+                        Op::ShiftLeft => &[0xd1, 0xe0],
+                        Op::ShiftRight => &[0xd1, 0xe8],
+                        Op::Not => &[0xf7, 0xd0],
+                        Op::Xor => &[0x44, 0x31, 0xf0],
+                        Op::And => &[0x44, 0x21, 0xf0],
+                        _ => &[0x44, 0x09, 0xf0],
+                    };
+
+                    asm.load_cell();
+                    asm.emit(bytes);
+                    asm.store_cell();
+                }
+            }
+        }
+
+        starts.push(asm.code.len());
+
+        let success = asm.code.len();
+
+        asm.emit(&[0x31, 0xc0]);
+
+        let epilogue = asm.code.len();
+
+        asm.emit(&[0x49, 0x89, 0x5d, 0x08, 0x4d, 0x89, 0x75, 0x10]);
+        asm.emit(&[0x41, 0x5f, 0x41, 0x5e, 0x41, 0x5d, 0x41, 0x5c, 0x5b, 0xc3]);
+
+        let failure = asm.code.len();
+
+        asm.emit(&[0xb8, 0x01, 0x00, 0x00, 0x00]);
+
+        let back = asm.jump(&[0xe9]);
+
+        asm.patch(back, epilogue);
+
+        for (at, pc) in jumps {
+            asm.patch(at, starts[pc]);
+        }
+
+        for at in exits {
+            asm.patch(at, success);
+        }
+
+        for at in failures {
+            asm.patch(at, failure);
+        }
+
+        return asm.code;
+    }
+
+    pub fn run<R: Read, W: Write>(program: &Program, config: Config, input: &mut R, output: &mut W) -> Result<(), BrainfuckError> {
+        check_data(program, &config)?;
+
+        let code = compile::<R, W>(program, &config);
+
+        let len = match config.tape {
+            TapeSize::Fixed(len) => len.max(1),
+            TapeSize::Dynamic => unreachable!(),
+        };
+
+        let width = match config.cell {
+            CellWidth::U8 => 1,
+            CellWidth::U16 => 2,
+            CellWidth::U32 => 4,
+        };
+
+        let mut tape = vec![0u8; len * width];
+
+        // Cells are little-endian, so each data byte is the low byte of its cell.
+        for (i, &byte) in program.data.iter().enumerate() {
+            tape[i * width] = byte;
+        }
+
+        let mut ctx = Context { tape: tape.as_mut_ptr(), ptr: 0, storage: 0, input, output, config, error: None };
+
+        let status = unsafe {
+            let memory = mmap(std::ptr::null_mut(), code.len(), PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+
+            if memory as isize == -1 {
+                return Err(BrainfuckError::Io(io::Error::last_os_error()));
+            }
+
+            std::ptr::copy_nonoverlapping(code.as_ptr(), memory as *mut u8, code.len());
+
+            if mprotect(memory, code.len(), PROT_READ | PROT_EXEC) != 0 {
+                munmap(memory, code.len());
+
+                return Err(BrainfuckError::Io(io::Error::last_os_error()));
+            }
+
+            let function: extern "C" fn(*mut Context<R, W>) -> i32 = std::mem::transmute(memory);
+
+            let status = function(&mut ctx);
+
+            munmap(memory, code.len());
+
+            status
+        };
+
+        if status != 0 {
+            return Err(BrainfuckError::Io(ctx.error.take().unwrap()));
+        }
+
+        ctx.output.flush()?;
+
+        return Ok(());
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+mod jit {
+    use super::*;
+
+    pub fn run<R: Read, W: Write>(program: &Program, config: Config, input: &mut R, output: &mut W) -> Result<(), BrainfuckError> {
+        check_data(program, &config)?;
+
+        let mut machine = Machine::new(config);
+
+        machine.load(&program.data);
+
+        let mut vm = Vm { program: program.clone(), machine, pc: 0, steps: 0, profile: None };
+
+        return vm.run(input, output);
+    }
+}
+
+// Decodes a byte stream as UTF-8 for display, holding back incomplete sequences and replacing invalid ones.
+struct Utf8Display<W: Write> {
+    inner: W,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Utf8Display<W> {
+    fn new(inner: W) -> Self {
+        return Utf8Display { inner, pending: vec![] };
+    }
+
+    fn drain(&mut self, finished: bool) -> io::Result<()> {
+        loop {
+            let (valid, invalid) = match std::str::from_utf8(&self.pending) {
+                Ok(_) => (self.pending.len(), None),
+                Err(err) => (err.valid_up_to(), Some(err.error_len())),
+            };
+
+            self.inner.write_all(&self.pending[..valid])?;
+
+            self.pending.drain(..valid);
+
+            match invalid {
+                None => return Ok(()),
+                Some(None) if !finished => return Ok(()),
+                Some(len) => {
+                    self.inner.write_all(char::REPLACEMENT_CHARACTER.to_string().as_bytes())?;
+
+                    let len = len.unwrap_or(self.pending.len());
+
+                    self.pending.drain(..len);
+                }
+            }
+        }
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.drain(true)?;
+
+        return self.inner.flush();
+    }
+}
+
+impl<W: Write> Write for Utf8Display<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+
+        self.drain(false)?;
+
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.inner.flush();
+    }
+}
+
+fn interpret<R: Read, W: Write>(code: &str, config: Config, input: &mut R, output: &mut W) -> Result<(), BrainfuckError> {
+    return Vm::new(code, config)?.run(input, output);
+}
+
+// Raw bytes go straight through when piped, but a terminal gets them decoded so stray bytes can't garble it.
+fn interpret_stdio(code: &str, config: Config) -> Result<(), BrainfuckError> {
+    let stdout = io::stdout();
+
+    if !stdout.is_terminal() || config.output == OutputFormat::Decimal {
+        return interpret(code, config, &mut io::stdin().lock(), &mut stdout.lock());
+    }
+
+    let mut display = Utf8Display::new(stdout.lock());
+
+    let result = interpret(code, config, &mut io::stdin().lock(), &mut display);
+
+    display.finish()?;
+
+    return result;
+}
+
+fn main() -> Result<(), BrainfuckError> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.len() >= 2 && args[0] == "debug" {
+        let code = std::fs::read_to_string(&args[1])?;
+
+        return match args.get(2) {
+            Some(path) => debug_repl(&code, Config::default(), &mut std::fs::File::open(path)?),
+            None => debug_repl(&code, Config::default(), &mut io::empty()),
+        };
+    }
+
+    if args.len() >= 3 && args[0] == "run" {
+        let mut vm = Vm::with_dialect(&std::fs::read_to_string(&args[2])?, &Dialect::named(&args[1])?, Config::default())?;
+
+        return vm.run(&mut io::stdin().lock(), &mut io::stdout().lock());
+    }
+
+    if args.len() >= 4 && args[0] == "translate" {
+        let code = std::fs::read_to_string(&args[3])?;
+
+        println!("{}", translate(&code, &Dialect::named(&args[1])?, &Dialect::named(&args[2])?)?);
+
+        return Ok(());
+    }
+
+    if args.len() >= 2 && args[0] == "minify" {
+        println!("{}", minify(&std::fs::read_to_string(&args[1])?, &Config::default())?);
+
+        return Ok(());
+    }
+
+    if args.len() >= 2 && args[0] == "analyze" {
+        for fact in analyze(&std::fs::read_to_string(&args[1])?, &Config::default())? {
+            println!("{}", fact);
+        }
+
+        return Ok(());
+    }
+
+    if args.len() >= 2 && args[0] == "compile" {
+        println!("{}", lang::compile(&std::fs::read_to_string(&args[1])?)?);
+
+        return Ok(());
+    }
+
+    if args.len() >= 2 && args[0] == "jit" {
+        return interpret_jit(&std::fs::read_to_string(&args[1])?, Config::default(), &mut io::stdin().lock(), &mut io::stdout().lock());
+    }
+
+    // Runs every program through both engines, feeding `<file>.in` as input when it exists.
+    if args.len() >= 2 && args[0] == "jit-check" {
+        let mut failed = 0;
+
+        for path in &args[1..] {
+            let code = std::fs::read_to_string(path)?;
+
+            let input = std::fs::read(format!("{}.in", path)).unwrap_or_default();
+
+            let (mut expected, mut actual) = (vec![], vec![]);
+
+            let start = Instant::now();
+
+            let interpreted = interpret(&code, Config::default(), &mut &input[..], &mut expected).map_err(|e| e.to_string());
+
+            let interpreter_time = start.elapsed();
+
+            let start = Instant::now();
+
+            let compiled = interpret_jit(&code, Config::default(), &mut &input[..], &mut actual).map_err(|e| e.to_string());
+
+            let jit_time = start.elapsed();
+
+            let same = expected == actual && interpreted == compiled;
+
+            if !same {
+                failed += 1;
+            }
+
+            println!("{} {} interpreter {:?} jit {:?}", if same { "ok  " } else { "FAIL" }, path, interpreter_time, jit_time);
+        }
+
+        println!("{} of {} programs differ", failed, args.len() - 1);
+
+        return Ok(());
+    }
+
+    if args.len() >= 2 && args[0] == "trace" {
+        return interpret_stdio(&std::fs::read_to_string(&args[1])?, Config { output: OutputFormat::Decimal, ..Config::default() });
+    }
+
+    if args.len() >= 2 && args[0] == "profile" {
+        let code = std::fs::read_to_string(&args[1])?;
+
+        let mut vm = Vm::new(&code, Config::default())?;
+
+        vm.enable_profiling();
+
+        vm.run(&mut io::stdin().lock(), &mut io::stdout().lock())?;
+
+        eprint!("{}", vm.profile.unwrap().annotate(&code, &vm.program));
+
+        return Ok(());
+    }
+
+    if args.len() >= 2 && (args[0] == "c" || args[0] == "rust") {
+        let code = std::fs::read_to_string(&args[1])?;
+
+        print!("{}", if args[0] == "c" { transpile_c(&code, &Config::default())? } else { transpile_rust(&code, &Config::default())? });
+
+        return Ok(());
+    }
+
+    if let Some(path) = args.first() {
+        return interpret_stdio(&std::fs::read_to_string(path)?, Config::default());
+    }
+
+    let mut output = vec![];
+
+    let config = Config {
+        cell: CellWidth::U16,
+        tape: TapeSize::Dynamic,
+        eof: EofPolicy::Zero,
+        ..Config::default()
+    };
+
+    interpret(",[.,]", config, &mut "echo".as_bytes(), &mut output)?;
+
+    println!("{:?}", String::from_utf8_lossy(&output));
+
+    if let Err(err) = interpret("+[\n]]+[", Config::default(), &mut io::empty(), &mut io::sink()) {
+        println!("{}", err);
+    }
+
+    let mut vms = [Vm::new("+[]", Config::default())?, Vm::new(HELLO_WORLD, Config::default())?];
+
+    let mut output = vec![];
+
+    for _ in 0..3 {
+        for vm in vms.iter_mut() {
+            let status = vm.run_for(Budget::steps(100), &mut io::empty(), &mut output)?;
+
+            println!("pc {} ptr {} {:?}", vm.pc, vm.machine.ptr, status);
+        }
+    }
+
+    println!("{:?}", String::from_utf8_lossy(&output));
+
+    let status = vms[0].run_for(Budget::time(Duration::from_millis(10)), &mut io::empty(), &mut io::sink())?;
+
+    println!("{:?} after {} steps", status, vms[0].steps);
+
+    let ook = translate(HELLO_WORLD, &Dialect::brainfuck(), &Dialect::ook())?;
+
+    println!("{}...", &ook[..44]);
+
+    println!("{}", translate(&ook, &Dialect::ook(), &Dialect::brainfuck())? == HELLO_WORLD);
+
+    let words = Dialect::new("words", InstructionSet::Classic, &[
+        ("right", '>'),
+        ("left", '<'),
+        ("up", '+'),
+        ("down", '-'),
+        ("say", '.'),
+        ("hear", ','),
+        ("while", '['),
+        ("end", ']'),
+    ]);
+
+    let mut output = vec![];
+
+    Vm::with_dialect("up up up up up up up up while right up up up up up up up up left down end right up say", &words, Config::default())?.run(&mut io::empty(), &mut output)?;
+
+    println!("{:?}", String::from_utf8_lossy(&output));
+
+    let mut output = vec![];
+
+    Vm::with_dialect("++++++++[>++++++++<-]>+$.}.{.~.!.@", &Dialect::extended(), Config::default())?.run(&mut io::empty(), &mut output)?;
+
+    println!("{:?}", String::from_utf8_lossy(&output));
+
+    let commented = "add two +++ then move >+<<>  [-]\n[loop >+<-] [never runs] +-- [>]";
+
+    println!("{}", minify(commented, &Config::default())?);
+
+    for fact in analyze(commented, &Config::default())? {
+        println!("{}", fact);
+    }
+
+    let mut output = vec![];
+
+    interpret(&lang::compile("x = 3; while x { print x + '0'; x = x - 1; }")?, Config::default(), &mut io::empty(), &mut output)?;
+
+    println!("{:?}", String::from_utf8_lossy(&output));
+
+    if let Err(err) = lang::compile("x = 1;\nprint y;") {
+        println!("{}", err);
+    }
+
+    let config = Config { step_limit: Some(1000), ..Config::default() };
+
+    if let Err(err) = interpret("+[]", config, &mut io::empty(), &mut io::sink()) {
+        println!("{}", err);
+    }
+
+    return interpret_stdio(HELLO_WORLD, Config::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Output and result of one engine, errors compared by message since io errors aren't comparable.
+    fn run_with(jit: bool, code: &str, config: Config, input: &[u8]) -> (Result<(), String>, Vec<u8>) {
+        let mut output = vec![];
+
+        let result = match jit {
+            true => interpret_jit(code, config, &mut &input[..], &mut output),
+            false => interpret(code, config, &mut &input[..], &mut output),
+        };
+
+        return (result.map_err(|err| err.to_string()), output);
+    }
+
+    fn assert_same(code: &str, config: Config, input: &[u8]) -> () {
+        let interpreted = run_with(false, code, config, input);
+
+        let compiled = run_with(true, code, config, input);
+
+        assert_eq!(interpreted, compiled, "{:?} with {:?}", code, config);
+    }
+
+    fn configs() -> Vec<Config> {
+        let mut configs = vec![];
+
+        for cell in [CellWidth::U8, CellWidth::U16, CellWidth::U32] {
+            for eof in [EofPolicy::Unchanged, EofPolicy::Zero, EofPolicy::Max] {
+                for tape in [TapeSize::Fixed(MEM_SIZE), TapeSize::Fixed(16)] {
+                    configs.push(Config { cell, eof, tape, ..Config::default() });
+                }
+            }
+        }
+
+        return configs;
+    }
+
+    // xorshift64, enough to make the fuzzed programs repeatable.
+    fn next(seed: &mut u64) -> u64 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+
+        return *seed;
+    }
+
+    fn random_program(seed: &mut u64, len: usize, symbols: &str) -> String {
+        let symbols: Vec<char> = symbols.chars().collect();
+
+        let mut code = String::new();
+
+        let mut depth = 0;
+
+        for _ in 0..len {
+            let symbol = symbols[next(seed) as usize % symbols.len()];
+
+            if symbol == ']' && depth == 0 {
+                continue;
+            }
+
+            depth += match symbol {
+                '[' => 1,
+                ']' => -1,
+                _ => 0,
+            };
+
+            code.push(symbol);
+        }
+
+        code.extend(std::iter::repeat_n(']', depth as usize));
+
+        return code;
+    }
+
+    #[test]
+    fn jit_matches_interpreter_on_sample_programs() {
+        let programs = [
+            HELLO_WORLD,
+            "-.>--.<[>+<-]>.",
+            "+++[>+++++<-]>[>++>+++<<-]>.>.",
+            ">>>+<<<+[>]>+.<<.",
+            "<+.<<-.>>>.",
+            "++++++++[>++++++++<-]>[-]+++.[-].",
+            ",>,<[->+<]>.",
+        ];
+
+        for config in configs() {
+            for program in programs {
+                assert_same(program, config, b"echo");
+                assert_same(program, config, b"");
+            }
+        }
+
+        // Input loops only end on the EOF value they expect.
+        for config in configs() {
+            match config.eof {
+                EofPolicy::Zero => assert_same(",[.,]", config, b"echo"),
+                EofPolicy::Max => assert_same(",+[-.,+]", config, b"echo"),
+                EofPolicy::Unchanged => assert_same(",[.[-],]", config, b"echo"),
+            }
+        }
+    }
+
+    #[test]
+    fn jit_matches_interpreter_on_extended_programs() {
+        let programs = ["++++++++[>++++++++<-]>+$.}.{.~.!.@ data", "+++$>++++&.|.^.!.", ",$>,^.<.@", "++++$[->+>+<<]>~.>!.@"];
+
+        for config in configs() {
+            for program in programs {
+                assert_same(program, Config { instructions: InstructionSet::Extended, ..config }, b"ab");
+            }
+        }
+    }
+
+    #[test]
+    fn extended_programs_start_with_their_data_on_the_tape() {
+        let config = Config { instructions: InstructionSet::Extended, ..Config::default() };
+
+        assert_eq!(run_with(false, "[.>]@data", config, b""), (Ok(()), b"data".to_vec()));
+        assert_eq!(run_with(false, "[.>]@data", Config { tape: TapeSize::Dynamic, ..config }, b""), (Ok(()), b"data".to_vec()));
+        assert_eq!(run_with(false, "[.>]@data", Config { tape: TapeSize::Fixed(2), ..config }, b"").0, Err("pointer out of range: 2".to_string()));
+
+        for config in configs() {
+            assert_same("[.>]@data", Config { instructions: InstructionSet::Extended, ..config }, b"");
+            assert_same("[.>]@data", Config { instructions: InstructionSet::Extended, tape: TapeSize::Fixed(2), ..config }, b"");
+        }
+
+        let mut output = vec![];
+
+        Vm::with_dialect("[.>]@data", &Dialect::extended(), Config::default()).unwrap().run(&mut io::empty(), &mut output).unwrap();
+
+        assert_eq!(output, b"data");
+
+        assert_eq!(minify("[.>]@data", &config).unwrap(), "[.>]@data");
+        assert_eq!(analyze("[.>]@data", &config).unwrap()[0].kind, LoopKind::Unbalanced(1));
+        assert_eq!(analyze("[.>]@\0data", &config).unwrap()[0].kind, LoopKind::Dead);
+        assert_eq!(Dialect::extended().render_core(&Dialect::extended().to_core("[.>]@data")).unwrap(), "[.>]@data");
+    }
+
+    #[test]
+    fn profile_counts_iterations_of_folded_loops() {
+        let mut vm = Vm::new("+++[-]>++++++[>++<-]>[>]", Config::default()).unwrap();
+
+        vm.enable_profiling();
+
+        vm.run(&mut io::empty(), &mut vec![]).unwrap();
+
+        let profile = vm.profile.unwrap();
+
+        let iterations: Vec<(Op, u64)> = (0..vm.program.ops.len()).filter(|pc| profile.iterations[*pc] > 0).map(|pc| (vm.program.ops[pc], profile.iterations[pc])).collect();
+
+        assert_eq!(iterations, [(Op::Clear, 3), (Op::MulAdd(1, 2), 6), (Op::Scan(1), 1)]);
+    }
+
+    #[test]
+    fn jit_matches_interpreter_on_decimal_output() {
+        assert_same(HELLO_WORLD, Config { output: OutputFormat::Decimal, ..Config::default() }, b"");
+    }
+
+    // Only programs the interpreter finishes within a step limit are compared, the JIT has no way to stop a runaway loop.
+    #[test]
+    fn jit_matches_interpreter_on_random_programs() {
+        let mut seed = 0x9e3779b97f4a7c15;
+
+        let mut compared = 0;
+
+        for round in 0..3000 {
+            let config = configs()[round % configs().len()];
+
+            let (symbols, instructions) = if round % 4 == 3 { ("+-<>.,[]$!{}~^&|", InstructionSet::Extended) } else { ("+-<>.,[]", InstructionSet::Classic) };
+
+            let config = Config { instructions, ..config };
+
+            let len = 10 + next(&mut seed) as usize % 60;
+
+            let code = random_program(&mut seed, len, symbols);
+
+            let input: Vec<u8> = (0..next(&mut seed) % 8).map(|_| next(&mut seed) as u8).collect();
+
+            let limited = Config { step_limit: Some(20_000), ..config };
+
+            if interpret(&code, limited, &mut &input[..], &mut io::sink()).is_err() {
+                continue;
+            }
+
+            assert_same(&code, config, &input);
+
+            compared += 1;
+        }
+
+        assert!(compared > 1000, "only {} programs finished", compared);
+    }
+
+    #[test]
+    fn minify_strips_comments_and_cancels_inverse_ops() {
+        assert_eq!(minify("add +++ take -- move >>< then +-", &Config::default()).unwrap(), "+>");
+    }
+
+    #[test]
+    fn minify_drops_a_loop_right_after_a_loop() {
+        assert_eq!(minify("+[-][never runs].", &Config::default()).unwrap(), "+[-].");
+    }
+
+    // The left edge of a dynamic tape grows the tape rather than wrapping to a fixed cell, so `<>` isn't a no-op there.
+    #[test]
+    fn minify_keeps_moves_on_a_dynamic_tape() {
+        let config = Config { tape: TapeSize::Dynamic, ..Config::default() };
+
+        assert_eq!(minify("+<>.", &config).unwrap(), "+<>.");
+
+        assert_same_minified("+<>.", config);
+    }
+
+    fn assert_same_minified(code: &str, config: Config) -> () {
+        let minified = minify(code, &config).unwrap();
+
+        assert_eq!(run_with(false, code, config, b"ab"), run_with(false, &minified, config, b"ab"), "{:?} minified to {:?} under {:?}", code, minified, config);
+    }
+
+    #[test]
+    fn minified_programs_behave_the_same() {
+        let programs = [HELLO_WORLD, "+<>.", "><+<<>.", "+[->+<]>.<-+>.", ",[.,] comment", "++[>+<-][-]>.", "+++[>]<.", "-<<+>.>>."];
+
+        let mut configs = configs();
+
+        configs.push(Config { tape: TapeSize::Dynamic, ..Config::default() });
+        configs.push(Config { tape: TapeSize::Dynamic, eof: EofPolicy::Zero, ..Config::default() });
+
+        for config in configs {
+            for program in programs {
+                if config.eof == EofPolicy::Zero || !program.contains(',') {
+                    assert_same_minified(program, config);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn analyze_reports_each_loop() {
+        let kinds: Vec<LoopKind> = analyze("+[-][>+<-]+[>]+[->>+<<]+[>+<-[-]]+[>>+]+[>[>]]", &Config::default()).unwrap().iter().map(|fact| fact.kind).collect();
+
+        assert_eq!(
+            kinds,
+            [
+                LoopKind::Clear,
+                LoopKind::Dead,
+                LoopKind::Scan(1),
+                LoopKind::Multiply,
+                LoopKind::Balanced,
+                LoopKind::Clear,
+                LoopKind::Unbalanced(2),
+                LoopKind::Unknown,
+                LoopKind::Scan(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn analyze_reports_positions() {
+        let facts = analyze("+\n [-]", &Config::default()).unwrap();
+
+        assert_eq!((facts[0].line, facts[0].column), (2, 2));
+    }
+
+    fn run_lang(source: &str) -> Vec<u8> {
+        let mut output = vec![];
+
+        interpret(&lang::compile(source).unwrap(), Config::default(), &mut io::empty(), &mut output).unwrap();
+
+        return output;
+    }
+
+    #[test]
+    fn lang_prints_strings() {
+        assert_eq!(run_lang("print \"Hi\\n\";"), b"Hi\n");
+    }
+
+    #[test]
+    fn lang_counts_down_in_a_loop() {
+        assert_eq!(run_lang("x = 3; while x { print x + '0'; x = x - 1; }"), b"321");
+    }
+
+    #[test]
+    fn lang_multiplies() {
+        assert_eq!(run_lang("a = 6; b = 7; print a * b;"), b"*");
+    }
+
+    #[test]
+    fn lang_takes_the_matching_branch() {
+        assert_eq!(run_lang("n = 5; if n == 5 { print \"five\"; } else { print \"other\"; }"), b"five");
+    }
+
+    #[test]
+    fn lang_keeps_variables_across_iterations() {
+        assert_eq!(run_lang("a = 0; b = 1; n = 8; while n { print a + 'a'; t = a + b; a = b; b = t; n = n - 1; }"), b"abbcdfin");
+    }
+
+    #[test]
+    fn lang_negates() {
+        assert_eq!(run_lang("x = 0; if !x { print \"zero\"; }"), b"zero");
+    }
+
+    #[test]
+    fn lang_skips_comments_and_compares_unequal() {
+        assert_eq!(run_lang("x = 2; // compared twice\nif x != 2 { print \"bad\"; } else { print (x + 1) * 3 + '0'; }"), b"9");
+    }
+
+    #[test]
+    fn lang_rejects_numbers_that_dont_fit_a_cell() {
+        assert_eq!(lang::compile("x = 300;\nprint x;").unwrap_err().to_string(), "syntax error on line 1: number out of range: 300");
+        assert_eq!(lang::compile("print '€';").unwrap_err().to_string(), "syntax error on line 1: number out of range: 8364");
+        assert_eq!(run_lang("print 255 + 66;"), b"A");
+    }
+
+    #[test]
+    fn lang_rejects_reads_before_the_first_assignment() {
+        assert_eq!(lang::compile("print y;\ny = 1;").unwrap_err().to_string(), "syntax error on line 1: variable 'y' is read before it is assigned");
+        assert_eq!(lang::compile("x = 1;\nwhile x { print y; y = x; x = 0; }").unwrap_err().to_string(), "syntax error on line 2: variable 'y' is read before it is assigned");
+        assert_eq!(lang::compile("x = x + 1;").unwrap_err().to_string(), "syntax error on line 1: variable 'x' is read before it is assigned");
+    }
 }
@@ -1,96 +1,2140 @@
 #![allow(dead_code)]
 
-const GRID_SIZE: i32 = 9;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
-fn is_in_row(board: Vec<Vec<i32>>, number: i32, row: i32) -> bool {
-    for i in 0..GRID_SIZE {
-        if board[row as usize][i as usize] == number {
-            return true;
+const GRID_SIZE: usize = 9;
+
+// A puzzle is a list of units, sets of `size` cells that must each hold every digit exactly once. Rows and columns are
+// always units; boxes, diagonals, windows and jigsaw regions are just more of them. Killer cages are kept apart since
+// they are smaller than a unit and add a sum on top.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rules {
+    size: usize,
+    boxes: Option<(usize, usize)>,
+    units: Vec<Vec<usize>>,
+    names: Vec<String>,
+    cell_units: Vec<Vec<usize>>,
+    cages: Vec<Cage>,
+    cell_cage: Vec<Option<usize>>,
+}
+
+impl Rules {
+    fn lines(size: usize) -> Rules {
+        let mut rules = Rules { size, boxes: None, units: vec![], names: vec![], cell_units: vec![vec![]; size * size], cages: vec![], cell_cage: vec![None; size * size] };
+
+        // Row i is unit 2i and column i is unit 2i + 1, the fish techniques rely on it.
+        for i in 0..size {
+            rules.add_unit(format!("row {}", i + 1), (0..size).map(|j| i * size + j).collect());
+            rules.add_unit(format!("column {}", i + 1), (0..size).map(|j| j * size + i).collect());
+        }
+
+        return rules;
+    }
+
+    // Boxes are `box_rows` tall and `box_cols` wide, so 2x3 boxes give a 6x6 grid.
+    fn new(box_rows: usize, box_cols: usize) -> Rules {
+        let size = box_rows * box_cols;
+
+        let mut rules = Rules::lines(size);
+
+        rules.boxes = Some((box_rows, box_cols));
+
+        for b in 0..size {
+            let (top, left) = (b / box_rows * box_rows, b % box_rows * box_cols);
+
+            rules.add_unit(format!("box {}", b + 1), (0..size).map(|i| (top + i / box_cols) * size + left + i % box_cols).collect());
+        }
+
+        return rules;
+    }
+
+    fn square(box_size: usize) -> Rules {
+        return Rules::new(box_size, box_size);
+    }
+
+    fn classic() -> Rules {
+        return Rules::square(3);
+    }
+
+    // Boxes as close to square as the size allows, wider than tall, so 6 gives 2x3 and 12 gives 3x4. None for sizes
+    // that only split into 1xN boxes.
+    fn for_size(size: usize) -> Option<Rules> {
+        let box_rows = (2..=size).take_while(|rows| rows * rows <= size).filter(|rows| size.is_multiple_of(*rows)).last()?;
+
+        return Some(Rules::new(box_rows, size / box_rows));
+    }
+
+    // None unless the map is square and every region id in 0..size covers exactly `size` cells.
+    fn jigsaw(regions: &[Vec<usize>]) -> Option<Rules> {
+        let size = regions.len();
+
+        if regions.iter().any(|row| row.len() != size) {
+            return None;
+        }
+
+        let mut rules = Rules::lines(size);
+
+        for region in 0..size {
+            let cells: Vec<usize> = regions.iter().flatten().enumerate().filter(|(_, r)| **r == region).map(|(cell, _)| cell).collect();
+
+            if cells.len() != size {
+                return None;
+            }
+
+            rules.add_unit(format!("region {}", region + 1), cells);
+        }
+
+        return Some(rules);
+    }
+
+    // X-Sudoku, both main diagonals hold every digit.
+    fn with_diagonals(mut self) -> Rules {
+        let size = self.size;
+
+        self.add_unit("the main diagonal".to_string(), (0..size).map(|i| i * size + i).collect());
+        self.add_unit("the anti-diagonal".to_string(), (0..size).map(|i| i * size + size - 1 - i).collect());
+
+        return self;
+    }
+
+    // Hyper or windoku, extra box-shaped windows sit one cell in from the box borders. Windows follow the box shape, so
+    // jigsaw layouts are left as they are.
+    fn with_windows(mut self) -> Rules {
+        let (box_rows, box_cols) = match self.boxes {
+            Some(boxes) => boxes,
+            None => return self,
+        };
+
+        let size = self.size;
+
+        let mut top = 1;
+
+        while top + box_rows <= size {
+            let mut left = 1;
+
+            while left + box_cols <= size {
+                let name = format!("window at r{}c{}", top + 1, left + 1);
+
+                self.add_unit(name, (0..size).map(|i| (top + i / box_cols) * size + left + i % box_cols).collect());
+
+                left += box_cols + 1;
+            }
+
+            top += box_rows + 1;
+        }
+
+        return self;
+    }
+
+    // Killer Sudoku, each (sum, cells) cage holds distinct digits adding up to its sum. A cell belongs to one cage at most.
+    fn with_cages(mut self, cages: &[(usize, Vec<usize>)]) -> Result<Rules, CageError> {
+        for (sum, cells) in cages {
+            let cage = self.cages.len();
+
+            for (i, &cell) in cells.iter().enumerate() {
+                if cell >= self.size * self.size {
+                    return Err(CageError::OutsideGrid { cage, cell });
+                }
+
+                if self.cell_cage[cell].is_some() || cells[..i].contains(&cell) {
+                    return Err(CageError::Overlap { cage, cell: self.cell_name(cell) });
+                }
+            }
+
+            let combos = sum_combinations(self.size, cells.len(), *sum);
+
+            if combos.is_empty() {
+                return Err(CageError::Unreachable { cage, sum: *sum, cells: cells.len() });
+            }
+
+            for &cell in cells {
+                self.cell_cage[cell] = Some(cage);
+            }
+
+            self.cages.push(Cage { sum: *sum, cells: cells.clone(), combos });
+        }
+
+        return Ok(self);
+    }
+
+    fn add_unit(&mut self, name: String, cells: Vec<usize>) -> () {
+        for &cell in &cells {
+            self.cell_units[cell].push(self.units.len());
+        }
+
+        self.units.push(cells);
+        self.names.push(name);
+    }
+
+    fn cell_name(&self, cell: usize) -> String {
+        return format!("r{}c{}", cell / self.size + 1, cell % self.size + 1);
+    }
+
+    // Two different cells see each other when they share a unit or a cage.
+    fn sees(&self, a: usize, b: usize) -> bool {
+        return a != b && (self.cell_units[a].iter().any(|unit| self.cell_units[b].contains(unit)) || self.cell_cage[a].is_some() && self.cell_cage[a] == self.cell_cage[b]);
+    }
+
+    // Names of the units, and the cage, that two cells share.
+    fn shared(&self, a: usize, b: usize) -> Vec<String> {
+        let mut names: Vec<String> = self.cell_units[a].iter().filter(|unit| self.cell_units[b].contains(unit)).map(|&unit| self.names[unit].clone()).collect();
+
+        if let Some(cage) = self.cell_cage[a].filter(|&cage| self.cell_cage[b] == Some(cage)) {
+            names.push(format!("cage {}", cage + 1));
+        }
+
+        return names;
+    }
+
+    // Bit d is set for every digit 1..=size, bit 0 is never used.
+    fn all_digits(&self) -> u32 {
+        return ((1u32 << self.size) - 1) << 1;
+    }
+
+    fn solutions(&self, board: &Board) -> Solutions {
+        return Solutions { stack: Grid::from_board(self, board).into_iter().map(|grid| Branch { grid, depth: 0, guess: None }).collect(), rules: self.clone(), rng: None, stats: None };
+    }
+
+    fn count_solutions(&self, board: &Board, limit: usize) -> usize {
+        return self.solutions(board).take(limit).count();
+    }
+
+    // Stops as soon as a second solution turns up.
+    fn has_unique_solution(&self, board: &Board) -> bool {
+        return self.count_solutions(board, 2) == 1;
+    }
+
+    fn solve(&self, board: &Board) -> Option<Board> {
+        return self.solutions(board).next();
+    }
+
+    // Like `solve`, also reporting how hard the search worked. With `trace` every guess, forced placement and undo is
+    // recorded as well.
+    fn solve_with_stats(&self, board: &Board, trace: bool) -> (Option<Board>, Stats) {
+        let start = Instant::now();
+
+        let mut solutions = self.solutions(board);
+
+        solutions.stats = Some(Stats { trace: if trace { Some(vec![]) } else { None }, ..Stats::default() });
+
+        let solved = solutions.next();
+
+        let mut stats = solutions.stats.take().unwrap();
+
+        stats.elapsed = start.elapsed();
+
+        return (solved, stats);
+    }
+}
+
+// A killer cage, its cells hold distinct digits adding up to `sum`. `combos` is the row of the sum-combination table for
+// this sum and cage size, every digit set that fits as a mask.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cage {
+    sum: usize,
+    cells: Vec<usize>,
+    combos: Vec<u32>,
+}
+
+impl Cage {
+    // Digits still open to the empty cells once the digits in `placed` are in.
+    fn allowed(&self, placed: u32) -> u32 {
+        return self.combos.iter().filter(|&&combo| combo & placed == placed).fold(0, |mask, &combo| mask | combo & !placed);
+    }
+
+    // Digits every combination that still fits needs, so they must go somewhere in the empty cells.
+    fn required(&self, placed: u32) -> u32 {
+        return self.combos.iter().copied().filter(|&combo| combo & placed == placed).reduce(|mask, combo| mask & combo).unwrap_or(0) & !placed;
+    }
+}
+
+// Every set of `count` distinct digits from 1..=size adding up to `sum`, as masks. 17 in two cells is only ever 8 and 9.
+fn sum_combinations(size: usize, count: usize, sum: usize) -> Vec<u32> {
+    fn extend(from: usize, size: usize, count: usize, sum: usize, mask: u32, combos: &mut Vec<u32>) -> () {
+        if count == 0 {
+            if sum == 0 {
+                combos.push(mask);
+            }
+
+            return;
+        }
+
+        for digit in from..=size.min(sum) {
+            extend(digit + 1, size, count - 1, sum - digit, mask | 1 << digit, combos);
+        }
+    }
+
+    let mut combos = vec![];
+
+    extend(1, size, count, sum, 0, &mut combos);
+
+    return combos;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CageError {
+    OutsideGrid { cage: usize, cell: usize },
+    Overlap { cage: usize, cell: String },
+    Unreachable { cage: usize, sum: usize, cells: usize },
+}
+
+impl fmt::Display for CageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CageError::OutsideGrid { cage, cell } => write!(f, "cage {} has cell {} outside the grid", cage + 1, cell),
+            CageError::Overlap { cage, cell } => write!(f, "cage {} takes {} which is already in a cage", cage + 1, cell),
+            CageError::Unreachable { cage, sum, cells } => write!(f, "cage {} can't add up to {} with {} distinct digits", cage + 1, sum, cells),
+        }
+    }
+}
+
+// A square grid of digits where 0 is a blank. Only `Board::new` builds one from outside input, so its shape and
+// digit range are always valid and its givens never repeat within a unit of the rules it was checked against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Board {
+    size: usize,
+    cells: Vec<u8>,
+}
+
+// Two givens holding the same digit, as (row, col) pairs, with the names of every unit they share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Conflict {
+    digit: u8,
+    first: (usize, usize),
+    second: (usize, usize),
+    units: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BoardError {
+    RowCount { found: usize, expected: usize },
+    RowLength { row: usize, found: usize, expected: usize },
+    OutOfRange { row: usize, col: usize, value: i32, size: usize },
+    Conflicts(Vec<Conflict>),
+}
+
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BoardError::RowCount { found, expected } => write!(f, "board has {} rows, expected {}", found, expected),
+            BoardError::RowLength { row, found, expected } => write!(f, "row {} has {} cells, expected {}", row + 1, found, expected),
+            BoardError::OutOfRange { row, col, value, size } => write!(f, "r{}c{} holds {}, expected 0 to {}", row + 1, col + 1, value, size),
+            BoardError::Conflicts(conflicts) => write!(f, "conflicting givens: {}", conflicts
+                .iter()
+                .map(|c| format!("{} at r{}c{} and r{}c{} ({})", c.digit, c.first.0 + 1, c.first.1 + 1, c.second.0 + 1, c.second.1 + 1, c.units.join(", ")))
+                .collect::<Vec<String>>()
+                .join("; ")),
+        }
+    }
+}
+
+impl Board {
+    fn new(rules: &Rules, rows: &[Vec<i32>]) -> Result<Board, BoardError> {
+        let size = rules.size;
+
+        if rows.len() != size {
+            return Err(BoardError::RowCount { found: rows.len(), expected: size });
+        }
+
+        let mut cells = vec![];
+
+        for (row, values) in rows.iter().enumerate() {
+            if values.len() != size {
+                return Err(BoardError::RowLength { row, found: values.len(), expected: size });
+            }
+
+            for (col, &value) in values.iter().enumerate() {
+                if !(0..=size as i32).contains(&value) {
+                    return Err(BoardError::OutOfRange { row, col, value, size });
+                }
+
+                cells.push(value as u8);
+            }
+        }
+
+        let board = Board { size, cells };
+
+        let conflicts = board.conflicts(rules);
+
+        if !conflicts.is_empty() {
+            return Err(BoardError::Conflicts(conflicts));
+        }
+
+        return Ok(board);
+    }
+
+    fn empty(size: usize) -> Board {
+        return Board { size, cells: vec![0; size * size] };
+    }
+
+    fn get(&self, row: usize, col: usize) -> u8 {
+        return self.cells[row * self.size + col];
+    }
+
+    fn rows(&self) -> Vec<Vec<i32>> {
+        return self.cells.chunks(self.size).map(|row| row.iter().map(|&d| d as i32).collect()).collect();
+    }
+
+    fn clues(&self) -> usize {
+        return self.cells.iter().filter(|&&d| d != 0).count();
+    }
+
+    // Every pair of equal givens sharing a unit or cage, each pair reported once.
+    fn conflicts(&self, rules: &Rules) -> Vec<Conflict> {
+        let mut conflicts: Vec<Conflict> = vec![];
+
+        for a in 0..self.cells.len() {
+            for b in a + 1..self.cells.len() {
+                if self.cells[a] == 0 || self.cells[a] != self.cells[b] || !rules.sees(a, b) {
+                    continue;
+                }
+
+                conflicts.push(Conflict { digit: self.cells[a], first: (a / self.size, a % self.size), second: (b / self.size, b % self.size), units: rules.shared(a, b) });
+            }
+        }
+
+        return conflicts;
+    }
+}
+
+// `used` holds one mask per unit of the digits already placed in it, `caged` the same per cage.
+#[derive(Debug, Clone)]
+struct Grid {
+    cells: Vec<u8>,
+    used: Vec<u32>,
+    caged: Vec<u32>,
+}
+
+impl Grid {
+    // None when the board was checked against rules of another size or its givens clash under these rules.
+    fn from_board(rules: &Rules, board: &Board) -> Option<Grid> {
+        if board.size != rules.size {
+            return None;
+        }
+
+        let mut grid = Grid { cells: vec![0; board.cells.len()], used: vec![0; rules.units.len()], caged: vec![0; rules.cages.len()] };
+
+        for (cell, &digit) in board.cells.iter().enumerate() {
+            if digit != 0 {
+                if grid.candidates(rules, cell) & 1 << digit == 0 {
+                    return None;
+                }
+
+                grid.place(rules, cell, digit);
+            }
+        }
+
+        return Some(grid);
+    }
+
+    fn to_board(&self, rules: &Rules) -> Board {
+        return Board { size: rules.size, cells: self.cells.clone() };
+    }
+
+    fn candidates(&self, rules: &Rules, cell: usize) -> u32 {
+        let mask = rules.cell_units[cell].iter().fold(rules.all_digits(), |mask, &unit| mask & !self.used[unit]);
+
+        return match rules.cell_cage[cell] {
+            Some(cage) => mask & rules.cages[cage].allowed(self.caged[cage]),
+            None => mask,
+        };
+    }
+
+    fn place(&mut self, rules: &Rules, cell: usize, digit: u8) -> () {
+        self.cells[cell] = digit;
+
+        for &unit in &rules.cell_units[cell] {
+            self.used[unit] |= 1 << digit;
+        }
+
+        if let Some(cage) = rules.cell_cage[cell] {
+            self.caged[cage] |= 1 << digit;
+        }
+    }
+
+    // Fills in naked and hidden singles, and digits a cage needs that only one of its cells can take, until none apply.
+    // Returns false on a contradiction.
+    fn propagate(&mut self, rules: &Rules) -> bool {
+        let mut changed = true;
+
+        while changed {
+            changed = false;
+
+            for cell in 0..self.cells.len() {
+                if self.cells[cell] != 0 {
+                    continue;
+                }
+
+                let candidates = self.candidates(rules, cell);
+
+                if candidates == 0 {
+                    return false;
+                }
+
+                if candidates.count_ones() == 1 {
+                    self.place(rules, cell, candidates.trailing_zeros() as u8);
+
+                    changed = true;
+                }
+            }
+
+            for unit in &rules.units {
+                let mut seen_once = 0u32;
+
+                let mut seen_twice = 0u32;
+
+                let mut placed = 0u32;
+
+                for &cell in unit {
+                    match self.cells[cell] {
+                        0 => {
+                            let candidates = self.candidates(rules, cell);
+
+                            seen_twice |= seen_once & candidates;
+                            seen_once |= candidates;
+                        }
+                        digit => placed |= 1 << digit,
+                    }
+                }
+
+                if (seen_once | placed) != rules.all_digits() {
+                    return false;
+                }
+
+                let hidden = seen_once & !seen_twice & !placed;
+
+                if hidden == 0 {
+                    continue;
+                }
+
+                for &cell in unit {
+                    let single = self.candidates(rules, cell) & hidden;
+
+                    if self.cells[cell] == 0 && single != 0 {
+                        if single.count_ones() > 1 {
+                            return false;
+                        }
+
+                        self.place(rules, cell, single.trailing_zeros() as u8);
+
+                        changed = true;
+                    }
+                }
+            }
+
+            for (c, cage) in rules.cages.iter().enumerate() {
+                for digit in digits_of(cage.required(self.caged[c])) {
+                    let places: Vec<usize> = cage.cells.iter().copied().filter(|&cell| self.cells[cell] == 0 && self.candidates(rules, cell) & 1 << digit != 0).collect();
+
+                    match places[..] {
+                        [] => return false,
+                        [cell] => {
+                            self.place(rules, cell, digit);
+
+                            changed = true;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        return true;
+    }
+}
+
+// A grid waiting on the search stack, with its depth and the guess that made it.
+struct Branch {
+    grid: Grid,
+    depth: usize,
+    guess: Option<(usize, u8)>,
+}
+
+// Depth-first search over an explicit stack, so solutions are produced one at a time as the caller asks for them.
+// With an rng the digits of each branch are tried in random order, and with stats the search keeps count of its work.
+struct Solutions {
+    rules: Rules,
+    stack: Vec<Branch>,
+    rng: Option<Rng>,
+    stats: Option<Stats>,
+}
+
+impl Solutions {
+    // Branches on the empty cell with the fewest candidates after propagation.
+    fn next_grid(&mut self) -> Option<Grid> {
+        let rules = &self.rules;
+
+        while let Some(Branch { mut grid, depth, guess }) = self.stack.pop() {
+            let before = self.stats.as_mut().map(|stats| {
+                stats.enter(depth, guess);
+
+                return grid.cells.clone();
+            });
+
+            let consistent = grid.propagate(rules);
+
+            if let (Some(stats), Some(before)) = (self.stats.as_mut(), before) {
+                stats.propagated(&before, &grid.cells, depth);
+            }
+
+            if !consistent {
+                continue;
+            }
+
+            let cell = (0..grid.cells.len())
+                .filter(|&cell| grid.cells[cell] == 0)
+                .min_by_key(|&cell| grid.candidates(rules, cell).count_ones());
+
+            let cell = match cell {
+                Some(cell) => cell,
+                None => return Some(grid),
+            };
+
+            let candidates = grid.candidates(rules, cell);
+
+            let mut digits: Vec<u8> = (1..=rules.size as u8).rev().filter(|d| candidates & 1 << d != 0).collect();
+
+            if let Some(rng) = self.rng.as_mut() {
+                rng.shuffle(&mut digits);
+            }
+
+            // Pushed highest digit first so the lowest is tried first.
+            for digit in digits {
+                let mut next = grid.clone();
+
+                next.place(rules, cell, digit);
+
+                self.stack.push(Branch { grid: next, depth: depth + 1, guess: Some((cell, digit)) });
+            }
+        }
+
+        if let Some(stats) = self.stats.as_mut() {
+            stats.unwind(0);
+        }
+
+        return None;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Event {
+    Guess { cell: usize, digit: u8, depth: usize },
+    Forced { cell: usize, digit: u8, depth: usize },
+    Undo { cell: usize, digit: u8, depth: usize, forced: bool },
+}
+
+impl Event {
+    fn describe(&self, rules: &Rules) -> String {
+        return match self {
+            Event::Guess { cell, digit, depth } => format!("{}guess {} = {}", "  ".repeat(*depth - 1), rules.cell_name(*cell), digit),
+            Event::Forced { cell, digit, depth } => format!("{}force {} = {}", "  ".repeat(*depth), rules.cell_name(*cell), digit),
+            Event::Undo { cell, digit, depth, forced } => format!("{}undo {} = {}", "  ".repeat(if *forced { *depth } else { *depth - 1 }), rules.cell_name(*cell), digit),
+        };
+    }
+}
+
+// Work done by one search. A node is a branch taken off the stack, a backtrack is a guess taken back, and propagations
+// count the digits filled in by singles rather than guessed. `path` holds the placements of the current line of search
+// as (depth, cell, digit, forced).
+#[derive(Debug, Clone, Default)]
+struct Stats {
+    nodes: usize,
+    backtracks: usize,
+    max_depth: usize,
+    propagations: usize,
+    elapsed: Duration,
+    trace: Option<Vec<Event>>,
+    path: Vec<(usize, usize, u8, bool)>,
+}
+
+impl Stats {
+    // A branch at `depth` replaces every placement at that depth or deeper.
+    fn enter(&mut self, depth: usize, guess: Option<(usize, u8)>) -> () {
+        self.nodes += 1;
+        self.max_depth = self.max_depth.max(depth);
+
+        self.unwind(depth);
+
+        if let Some((cell, digit)) = guess {
+            self.path.push((depth, cell, digit, false));
+            self.record(Event::Guess { cell, digit, depth });
+        }
+    }
+
+    // Takes back the placements at `depth` or deeper, latest first. Only guesses count as backtracks.
+    fn unwind(&mut self, depth: usize) -> () {
+        while let Some(&(at, cell, digit, forced)) = self.path.last() {
+            if at < depth {
+                break;
+            }
+
+            self.path.pop();
+
+            if !forced {
+                self.backtracks += 1;
+            }
+
+            self.record(Event::Undo { cell, digit, depth: at, forced });
+        }
+    }
+
+    fn propagated(&mut self, before: &[u8], after: &[u8], depth: usize) -> () {
+        for cell in 0..before.len() {
+            if before[cell] != after[cell] {
+                self.propagations += 1;
+                self.path.push((depth, cell, after[cell], true));
+                self.record(Event::Forced { cell, digit: after[cell], depth });
+            }
+        }
+    }
+
+    fn record(&mut self, event: Event) -> () {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(event);
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes, {} backtracks, max depth {}, {} propagated in {:?}",
+            self.nodes, self.backtracks, self.max_depth, self.propagations, self.elapsed
+        )
+    }
+}
+
+impl Iterator for Solutions {
+    type Item = Board;
+
+    fn next(&mut self) -> Option<Board> {
+        return self.next_grid().map(|grid| grid.to_board(&self.rules));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ParseError {
+    Empty,
+    NotSquare { line: usize, cells: usize },
+    UnknownSymbol { line: usize, column: usize, symbol: char },
+    DigitOutOfRange { line: usize, column: usize, digit: i32, size: usize },
+    RowLength { line: usize, found: usize, expected: usize },
+    RowCount { found: usize, expected: usize },
+    BadSum { line: usize, token: String },
+    UnknownCell { line: usize, token: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "no puzzle found"),
+            ParseError::NotSquare { line, cells } => write!(f, "{} cells on line {} can't make a square grid", cells, line),
+            ParseError::UnknownSymbol { line, column, symbol } => write!(f, "unknown symbol '{}' at {}:{}", symbol, line, column),
+            ParseError::DigitOutOfRange { line, column, digit, size } => write!(f, "digit {} at {}:{} doesn't fit a {}x{} grid", digit, line, column, size, size),
+            ParseError::RowLength { line, found, expected } => write!(f, "row on line {} has {} cells, expected {}", line, found, expected),
+            ParseError::RowCount { found, expected } => write!(f, "found {} rows, expected {}", found, expected),
+            ParseError::BadSum { line, token } => write!(f, "'{}' on line {} isn't a cage sum", token, line),
+            ParseError::UnknownCell { line, token } => write!(f, "'{}' on line {} isn't a cell of the grid", token, line),
+        }
+    }
+}
+
+// `.` and `0` are blanks, digits above 9 are written as letters so 16x16 and 25x25 grids stay one symbol per cell.
+fn symbol_value(symbol: char) -> Option<i32> {
+    return match symbol {
+        '.' => Some(0),
+        '0'..='9' => symbol.to_digit(10).map(|d| d as i32),
+        'a'..='z' | 'A'..='Z' => Some(symbol.to_ascii_uppercase() as i32 - 'A' as i32 + 10),
+        _ => None,
+    };
+}
+
+fn value_symbol(value: i32) -> char {
+    return match value {
+        0 => '.',
+        1..=9 => (b'0' + value as u8) as char,
+        _ => (b'A' + (value - 10) as u8) as char,
+    };
+}
+
+fn parse_cells(row: &[(usize, char)], line: usize, size: usize) -> Result<Vec<i32>, ParseError> {
+    let mut cells = vec![];
+
+    for &(column, symbol) in row {
+        let digit = symbol_value(symbol).ok_or(ParseError::UnknownSymbol { line, column, symbol })?;
+
+        if digit as usize > size {
+            return Err(ParseError::DigitOutOfRange { line, column, digit, size });
+        }
+
+        cells.push(digit);
+    }
+
+    return Ok(cells);
+}
+
+// The single-line format, e.g. 81 characters for a 9x9 grid read row by row.
+fn parse_line(text: &str) -> Result<Vec<Vec<i32>>, ParseError> {
+    return parse_numbered_line(text.trim(), 1);
+}
+
+fn parse_numbered_line(text: &str, line: usize) -> Result<Vec<Vec<i32>>, ParseError> {
+    let symbols: Vec<(usize, char)> = text.chars().enumerate().map(|(i, c)| (i + 1, c)).collect();
+
+    if symbols.is_empty() {
+        return Err(ParseError::Empty);
+    }
+
+    let size = (symbols.len() as f64).sqrt() as usize;
+
+    if size * size != symbols.len() {
+        return Err(ParseError::NotSquare { line, cells: symbols.len() });
+    }
+
+    return symbols.chunks(size).map(|row| parse_cells(row, line, size)).collect();
+}
+
+// One row per line, ignoring whitespace, `|`, `+`, `-` and `=` so boxed layouts like `format_grid` output read back in.
+// Lines holding nothing but separators are skipped.
+fn parse_grid(text: &str) -> Result<Vec<Vec<i32>>, ParseError> {
+    let rows: Vec<(usize, Vec<(usize, char)>)> = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.chars().enumerate().map(|(j, c)| (j + 1, c)).filter(|(_, c)| !c.is_whitespace() && !"|+-=".contains(*c)).collect::<Vec<(usize, char)>>()))
+        .filter(|(_, row)| !row.is_empty())
+        .collect();
+
+    if rows.is_empty() {
+        return Err(ParseError::Empty);
+    }
+
+    let size = rows.len();
+
+    let mut board = vec![];
+
+    for (line, row) in &rows {
+        if row.len() != size {
+            return Err(ParseError::RowLength { line: *line, found: row.len(), expected: size });
+        }
+
+        board.push(parse_cells(row, *line, size)?);
+    }
+
+    return Ok(board);
+}
+
+// One single-line puzzle per line. Blank lines and lines starting with `#` are skipped, and anything after the puzzle
+// on the same line is treated as a comment.
+fn parse_batch(text: &str) -> Result<Vec<Vec<Vec<i32>>>, ParseError> {
+    let mut boards = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        boards.push(parse_numbered_line(line.split_whitespace().next().unwrap(), i + 1)?);
+    }
+
+    return Ok(boards);
+}
+
+// One killer cage per line, its sum and then its cells, e.g. `15: r1c1 r1c2 r2c1`. Blank lines and lines starting with
+// `#` are skipped as in batch files.
+fn parse_cages(text: &str, size: usize) -> Result<Vec<(usize, Vec<usize>)>, ParseError> {
+    let mut cages = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut tokens = line.split_whitespace();
+
+        let token = tokens.next().unwrap();
+
+        let sum = token.trim_end_matches(':').parse().map_err(|_| ParseError::BadSum { line: i + 1, token: token.to_string() })?;
+
+        let cells = tokens
+            .map(|token| parse_cell(token, size).ok_or_else(|| ParseError::UnknownCell { line: i + 1, token: token.to_string() }))
+            .collect::<Result<Vec<usize>, ParseError>>()?;
+
+        cages.push((sum, cells));
+    }
+
+    if cages.is_empty() {
+        return Err(ParseError::Empty);
+    }
+
+    return Ok(cages);
+}
+
+// `r3c7` style names, both 1-based, as written by `Rules::cell_name`.
+fn parse_cell(token: &str, size: usize) -> Option<usize> {
+    let (row, col) = token.strip_prefix(['r', 'R'])?.split_once(['c', 'C'])?;
+
+    let (row, col): (usize, usize) = (row.parse().ok()?, col.parse().ok()?);
+
+    if !(1..=size).contains(&row) || !(1..=size).contains(&col) {
+        return None;
+    }
+
+    return Some((row - 1) * size + col - 1);
+}
+
+fn format_cages(rules: &Rules) -> String {
+    return rules
+        .cages
+        .iter()
+        .map(|cage| format!("{}: {}\n", cage.sum, cage.cells.iter().map(|&cell| rules.cell_name(cell)).collect::<Vec<String>>().join(" ")))
+        .collect();
+}
+
+fn format_line(board: &Board) -> String {
+    return board.cells.iter().map(|&value| value_symbol(value as i32)).collect();
+}
+
+// Draws box borders when the rules have boxes, jigsaw grids are printed without them.
+fn format_grid(board: &Board, rules: &Rules) -> String {
+    let (box_rows, box_cols) = rules.boxes.unwrap_or((rules.size, rules.size));
+
+    let rule = vec!["-".repeat(box_cols * 2 - 1); rules.size / box_cols].join("-+-");
+
+    let mut text = String::new();
+
+    for (i, row) in board.rows().iter().enumerate() {
+        if i > 0 && i % box_rows == 0 {
+            text += &rule;
+            text += "\n";
+        }
+
+        let boxes: Vec<String> = row
+            .chunks(box_cols)
+            .map(|chunk| chunk.iter().map(|&value| value_symbol(value).to_string()).collect::<Vec<String>>().join(" "))
+            .collect();
+
+        text += &boxes.join(" | ");
+        text += "\n";
+    }
+
+    return text;
+}
+
+fn solutions(board: &Board) -> Solutions {
+    return Rules::classic().solutions(board);
+}
+
+fn count_solutions(board: &Board, limit: usize) -> usize {
+    return Rules::classic().count_solutions(board, limit);
+}
+
+fn has_unique_solution(board: &Board) -> bool {
+    return Rules::classic().has_unique_solution(board);
+}
+
+fn can_be_solved(board: &Board) -> bool {
+    return solutions(board).next().is_some();
+}
+
+fn solve(board: &Board) -> Option<Board> {
+    return Rules::classic().solve(board);
+}
+
+fn solve_with_stats(board: &Board, trace: bool) -> (Option<Board>, Stats) {
+    return Rules::classic().solve_with_stats(board, trace);
+}
+
+fn can_be_solved_with_stats(board: &Board) -> (bool, Stats) {
+    let (solved, stats) = solve_with_stats(board, false);
+
+    return (solved.is_some(), stats);
+}
+
+// A search strategy for boards under some rules, so engines can be picked per job and checked against each other.
+trait Engine {
+    fn name(&self) -> &'static str;
+
+    fn solutions(&self, rules: &Rules, board: &Board) -> Box<dyn Iterator<Item = Board>>;
+
+    fn count_solutions(&self, rules: &Rules, board: &Board, limit: usize) -> usize {
+        return self.solutions(rules, board).take(limit).count();
+    }
+
+    fn solve(&self, rules: &Rules, board: &Board) -> Option<Board> {
+        return self.solutions(rules, board).next();
+    }
+}
+
+// Propagation with minimum-remaining-values branching, the search behind `Rules::solutions`.
+struct Backtracking;
+
+impl Engine for Backtracking {
+    fn name(&self) -> &'static str {
+        return "backtracking";
+    }
+
+    fn solutions(&self, rules: &Rules, board: &Board) -> Box<dyn Iterator<Item = Board>> {
+        return Box::new(rules.solutions(board));
+    }
+}
+
+// Knuth's Algorithm X over dancing links, see `Cover`.
+struct DancingLinks;
+
+impl Engine for DancingLinks {
+    fn name(&self) -> &'static str {
+        return "dancing links";
+    }
+
+    fn solutions(&self, rules: &Rules, board: &Board) -> Box<dyn Iterator<Item = Board>> {
+        return Box::new(Cover::new(rules, board));
+    }
+}
+
+// The puzzle as an exact cover problem. Each (cell, digit) is a row covering the cell's column and a (unit, digit)
+// column for every unit the cell is in, and a solution is a set of rows covering every column exactly once. Cages
+// can't repeat a digit but needn't hold them all, so their (cage, digit) columns are secondary: left out of the
+// header list so they're never chosen, only covered by the rows that use them. Cage sums don't fit the matrix and are
+// checked as rows are picked instead.
+//
+// Node 0 is the root and node c + 1 the header of column c, row nodes follow. The search runs on an explicit stack
+// of the row node picked in each chosen column, or the header itself before the first pick, so solutions come out
+// one at a time.
+struct Cover {
+    rules: Rules,
+    left: Vec<usize>,
+    right: Vec<usize>,
+    up: Vec<usize>,
+    down: Vec<usize>,
+    header: Vec<usize>,
+    size: Vec<usize>,
+    choice: Vec<(usize, u8)>,
+    cells: Vec<u8>,
+    caged: Vec<u32>,
+    stack: Vec<usize>,
+    descend: bool,
+    finished: bool,
+}
+
+impl Cover {
+    fn new(rules: &Rules, board: &Board) -> Cover {
+        let size = rules.size;
+
+        let cells = size * size;
+
+        let primary = cells + rules.units.len() * size;
+
+        let columns = primary + rules.cages.len() * size;
+
+        let mut cover = Cover {
+            rules: rules.clone(),
+            left: vec![],
+            right: vec![],
+            up: vec![],
+            down: vec![],
+            header: vec![],
+            size: vec![0; columns + 1],
+            choice: vec![],
+            cells: vec![0; cells],
+            caged: vec![0; rules.cages.len()],
+            stack: vec![],
+            descend: true,
+            finished: false,
+        };
+
+        for node in 0..=columns {
+            cover.left.push(if node == 0 { primary } else if node > primary { node } else { node - 1 });
+            cover.right.push(if node == primary { 0 } else if node > primary { node } else { node + 1 });
+            cover.up.push(node);
+            cover.down.push(node);
+            cover.header.push(node);
+            cover.choice.push((0, 0));
+        }
+
+        // Givens get a single row and clashing digits none, the search still has to pick the givens' rows.
+        let grid = match Grid::from_board(rules, board) {
+            Some(grid) => grid,
+            None => {
+                cover.finished = true;
+
+                return cover;
+            }
+        };
+
+        for cell in 0..cells {
+            let digits = match grid.cells[cell] {
+                0 => grid.candidates(rules, cell),
+                digit => 1 << digit,
+            };
+
+            for digit in digits_of(digits) {
+                let mut row = vec![cell];
+
+                row.extend(rules.cell_units[cell].iter().map(|&unit| cells + unit * size + digit as usize - 1));
+
+                if let Some(cage) = rules.cell_cage[cell] {
+                    row.push(primary + cage * size + digit as usize - 1);
+                }
+
+                cover.add_row(&row, (cell, digit));
+            }
+        }
+
+        return cover;
+    }
+
+    fn add_row(&mut self, columns: &[usize], choice: (usize, u8)) -> () {
+        let first = self.header.len();
+
+        for (i, &column) in columns.iter().enumerate() {
+            let (node, header) = (first + i, column + 1);
+
+            self.left.push(if i == 0 { first + columns.len() - 1 } else { node - 1 });
+            self.right.push(if i + 1 == columns.len() { first } else { node + 1 });
+            self.up.push(self.up[header]);
+            self.down.push(header);
+            self.header.push(header);
+            self.choice.push(choice);
+
+            let last = self.up[header];
+
+            self.down[last] = node;
+            self.up[header] = node;
+            self.size[header] += 1;
+        }
+    }
+
+    // Takes a column out of the header list along with every row that uses it.
+    fn cover(&mut self, header: usize) -> () {
+        let (left, right) = (self.left[header], self.right[header]);
+
+        self.right[left] = right;
+        self.left[right] = left;
+
+        let mut row = self.down[header];
+
+        while row != header {
+            let mut node = self.right[row];
+
+            while node != row {
+                let (up, down) = (self.up[node], self.down[node]);
+
+                self.down[up] = down;
+                self.up[down] = up;
+                self.size[self.header[node]] -= 1;
+
+                node = self.right[node];
+            }
+
+            row = self.down[row];
+        }
+    }
+
+    // Exactly undoes `cover`, in reverse order.
+    fn uncover(&mut self, header: usize) -> () {
+        let mut row = self.up[header];
+
+        while row != header {
+            let mut node = self.left[row];
+
+            while node != row {
+                let (up, down) = (self.up[node], self.down[node]);
+
+                self.down[up] = node;
+                self.up[down] = node;
+                self.size[self.header[node]] += 1;
+
+                node = self.left[node];
+            }
+
+            row = self.up[row];
+        }
+
+        let (left, right) = (self.left[header], self.right[header]);
+
+        self.right[left] = header;
+        self.left[right] = header;
+    }
+
+    // Whether a cage can still reach its sum with this row's digit in it.
+    fn fits(&self, row: usize) -> bool {
+        let (cell, digit) = self.choice[row];
+
+        return match self.rules.cell_cage[cell] {
+            Some(cage) => self.rules.cages[cage].allowed(self.caged[cage]) & 1 << digit != 0,
+            None => true,
+        };
+    }
+
+    fn pick(&mut self, row: usize) -> () {
+        let (cell, digit) = self.choice[row];
+
+        self.cells[cell] = digit;
+
+        if let Some(cage) = self.rules.cell_cage[cell] {
+            self.caged[cage] |= 1 << digit;
+        }
+
+        let mut node = self.right[row];
+
+        while node != row {
+            self.cover(self.header[node]);
+
+            node = self.right[node];
+        }
+    }
+
+    fn unpick(&mut self, row: usize) -> () {
+        let (cell, digit) = self.choice[row];
+
+        self.cells[cell] = 0;
+
+        if let Some(cage) = self.rules.cell_cage[cell] {
+            self.caged[cage] &= !(1 << digit);
+        }
+
+        let mut node = self.left[row];
+
+        while node != row {
+            self.uncover(self.header[node]);
+
+            node = self.left[node];
+        }
+    }
+}
+
+impl Iterator for Cover {
+    type Item = Board;
+
+    fn next(&mut self) -> Option<Board> {
+        while !self.finished {
+            if self.descend {
+                if self.right[0] == 0 {
+                    self.descend = false;
+
+                    return Some(Board { size: self.rules.size, cells: self.cells.clone() });
+                }
+
+                // Branches on the column with the fewest rows left.
+                let mut best = self.right[0];
+
+                let mut header = self.right[best];
+
+                while header != 0 {
+                    if self.size[header] < self.size[best] {
+                        best = header;
+                    }
+
+                    header = self.right[header];
+                }
+
+                self.cover(best);
+
+                self.stack.push(best);
+            }
+
+            // Moves the deepest choice on to its next row, backing out of the column once its rows run out.
+            let row = match self.stack.pop() {
+                Some(row) => row,
+                None => {
+                    self.finished = true;
+
+                    return None;
+                }
+            };
+
+            let header = self.header[row];
+
+            if row != header {
+                self.unpick(row);
+            }
+
+            let mut next = self.down[row];
+
+            while next != header && !self.fits(next) {
+                next = self.down[next];
+            }
+
+            if next == header {
+                self.uncover(header);
+
+                self.descend = false;
+
+                continue;
+            }
+
+            self.pick(next);
+
+            self.stack.push(next);
+
+            self.descend = true;
+        }
+
+        return None;
+    }
+}
+
+// Ordered from easiest to hardest for a human solver. Guess is the search fallback once no technique applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Technique {
+    HiddenSingle,
+    NakedSingle,
+    LockedCandidates,
+    NakedPair,
+    HiddenPair,
+    NakedTriple,
+    HiddenTriple,
+    XWing,
+    Swordfish,
+    XYWing,
+    Guess,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+
+impl Technique {
+    fn difficulty(&self) -> Difficulty {
+        return match self {
+            Technique::HiddenSingle => Difficulty::Easy,
+            Technique::NakedSingle => Difficulty::Medium,
+            Technique::LockedCandidates | Technique::NakedPair | Technique::HiddenPair | Technique::NakedTriple | Technique::HiddenTriple => Difficulty::Hard,
+            Technique::XWing | Technique::Swordfish | Technique::XYWing | Technique::Guess => Difficulty::Expert,
+        };
+    }
+}
+
+// One deduction: the units and cells that form the pattern, the digits it is about, and what it places or rules out
+// as (cell, digit) pairs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    technique: Technique,
+    units: Vec<usize>,
+    cells: Vec<usize>,
+    digits: Vec<u8>,
+    placed: Vec<(usize, u8)>,
+    eliminated: Vec<(usize, u8)>,
+}
+
+impl Step {
+    fn explain(&self, rules: &Rules) -> String {
+        let cells = self.cells.iter().map(|&cell| rules.cell_name(cell)).collect::<Vec<String>>().join(", ");
+
+        let digits = self.digits.iter().map(|d| d.to_string()).collect::<Vec<String>>().join(", ");
+
+        let units = self.units.iter().map(|&unit| rules.names[unit].clone()).collect::<Vec<String>>().join(", ");
+
+        let removed = self.eliminated.iter().map(|&(cell, digit)| format!("{} from {}", digit, rules.cell_name(cell))).collect::<Vec<String>>().join(", ");
+
+        return match self.technique {
+            Technique::HiddenSingle => format!("hidden single: {} is the only place for {} in {}", cells, digits, units),
+            Technique::NakedSingle => format!("naked single: {} can only be {}", cells, digits),
+            Technique::LockedCandidates => format!("locked candidates: in {} every {} lies in {} which is also in {}, so remove {}", rules.names[self.units[0]], digits, cells, rules.names[self.units[1]], removed),
+            Technique::NakedPair | Technique::NakedTriple => format!("{}: {} in {} only hold {}, so remove {}", self.name(), cells, units, digits, removed),
+            Technique::HiddenPair | Technique::HiddenTriple => format!("{}: in {} the digits {} only fit in {}, so remove {}", self.name(), units, digits, cells, removed),
+            Technique::XWing | Technique::Swordfish => format!("{} on {}: {} only allow it in {}, so remove {}", self.name(), digits, units, cells, removed),
+            Technique::XYWing => format!("XY-wing: pivot {} with wings {}, whichever wing holds {} rules it out of cells seeing both, so remove {}", rules.cell_name(self.cells[0]), rules.cell_name(self.cells[1]) + " and " + &rules.cell_name(self.cells[2]), digits, removed),
+            Technique::Guess => format!("guess: no technique applies, the solution has {} in {}", digits, cells),
+        };
+    }
+
+    fn name(&self) -> &'static str {
+        return match self.technique {
+            Technique::NakedPair => "naked pair",
+            Technique::NakedTriple => "naked triple",
+            Technique::HiddenPair => "hidden pair",
+            Technique::HiddenTriple => "hidden triple",
+            Technique::XWing => "X-wing",
+            _ => "swordfish",
+        };
+    }
+}
+
+fn digits_of(mask: u32) -> Vec<u8> {
+    return (1..32).filter(|d| mask & 1 << d != 0).collect();
+}
+
+// Solves the way a person would, one named deduction at a time over explicit candidates.
+struct Logic<'a> {
+    rules: &'a Rules,
+    cells: Vec<u8>,
+    candidates: Vec<u32>,
+}
+
+impl<'a> Logic<'a> {
+    fn new(rules: &'a Rules, board: &Board) -> Option<Logic<'a>> {
+        let grid = Grid::from_board(rules, board)?;
+
+        let candidates = (0..grid.cells.len()).map(|cell| if grid.cells[cell] == 0 { grid.candidates(rules, cell) } else { 0 }).collect();
+
+        return Some(Logic { rules, cells: grid.cells, candidates });
+    }
+
+    fn is_solved(&self) -> bool {
+        return self.cells.iter().all(|&digit| digit != 0);
+    }
+
+    fn apply(&mut self, step: &Step) -> () {
+        for &(cell, digit) in &step.placed {
+            self.cells[cell] = digit;
+            self.candidates[cell] = 0;
+
+            for &unit in &self.rules.cell_units[cell] {
+                for &peer in &self.rules.units[unit] {
+                    self.candidates[peer] &= !(1 << digit);
+                }
+            }
+
+            if let Some(cage) = self.rules.cell_cage[cell] {
+                for &peer in &self.rules.cages[cage].cells {
+                    self.candidates[peer] &= !(1 << digit);
+                }
+            }
+        }
+
+        for &(cell, digit) in &step.eliminated {
+            self.candidates[cell] &= !(1 << digit);
+        }
+    }
+
+    // Cells of `unit` that still have `digit` as a candidate.
+    fn places(&self, unit: usize, digit: u8) -> Vec<usize> {
+        return self.rules.units[unit].iter().copied().filter(|&cell| self.candidates[cell] & 1 << digit != 0).collect();
+    }
+
+    // Every (cell, digit) among `cells` that is still a candidate and lies in `mask`.
+    fn candidates_in(&self, cells: impl Iterator<Item = usize>, mask: u32) -> Vec<(usize, u8)> {
+        return cells.flat_map(|cell| digits_of(self.candidates[cell] & mask).into_iter().map(move |digit| (cell, digit))).collect();
+    }
+
+    fn hidden_single(&self) -> Option<Step> {
+        for unit in 0..self.rules.units.len() {
+            for digit in 1..=self.rules.size as u8 {
+                if let [cell] = self.places(unit, digit)[..] {
+                    return Some(Step { technique: Technique::HiddenSingle, units: vec![unit], cells: vec![cell], digits: vec![digit], placed: vec![(cell, digit)], eliminated: vec![] });
+                }
+            }
+        }
+
+        return None;
+    }
+
+    fn naked_single(&self) -> Option<Step> {
+        let cell = (0..self.cells.len()).find(|&cell| self.candidates[cell].count_ones() == 1)?;
+
+        let digit = self.candidates[cell].trailing_zeros() as u8;
+
+        return Some(Step { technique: Technique::NakedSingle, units: vec![], cells: vec![cell], digits: vec![digit], placed: vec![(cell, digit)], eliminated: vec![] });
+    }
+
+    // When a digit's places in one unit all fall inside another unit, it can't go anywhere else in that other unit.
+    // Covers both pointing (box to line) and box/line reduction (line to box), and works for variant units too.
+    fn locked_candidates(&self) -> Option<Step> {
+        for unit in 0..self.rules.units.len() {
+            for digit in 1..=self.rules.size as u8 {
+                let places = self.places(unit, digit);
+
+                if places.len() < 2 {
+                    continue;
+                }
+
+                for &other in &self.rules.cell_units[places[0]] {
+                    if other == unit || !places.iter().all(|cell| self.rules.cell_units[*cell].contains(&other)) {
+                        continue;
+                    }
+
+                    let eliminated = self.candidates_in(self.places(other, digit).into_iter().filter(|cell| !places.contains(cell)), 1 << digit);
+
+                    if !eliminated.is_empty() {
+                        return Some(Step { technique: Technique::LockedCandidates, units: vec![unit, other], cells: places, digits: vec![digit], placed: vec![], eliminated });
+                    }
+                }
+            }
+        }
+
+        return None;
+    }
+
+    // `count` cells of a unit sharing exactly `count` candidates between them own those digits within the unit.
+    fn naked_subset(&self, count: usize, technique: Technique) -> Option<Step> {
+        for (index, unit) in self.rules.units.iter().enumerate() {
+            let open: Vec<usize> = unit.iter().copied().filter(|&cell| (2..=count as u32).contains(&self.candidates[cell].count_ones())).collect();
+
+            for subset in combinations(&open, count) {
+                let digits = subset.iter().fold(0, |mask, &cell| mask | self.candidates[cell]);
+
+                if digits.count_ones() as usize != count {
+                    continue;
+                }
+
+                let eliminated = self.candidates_in(unit.iter().copied().filter(|cell| !subset.contains(cell)), digits);
+
+                if !eliminated.is_empty() {
+                    return Some(Step { technique, units: vec![index], cells: subset, digits: digits_of(digits), placed: vec![], eliminated });
+                }
+            }
+        }
+
+        return None;
+    }
+
+    // `count` digits that only fit in the same `count` cells of a unit push every other candidate out of those cells.
+    fn hidden_subset(&self, count: usize, technique: Technique) -> Option<Step> {
+        for index in 0..self.rules.units.len() {
+            let open: Vec<usize> = (1..=self.rules.size).filter(|&d| (2..=count).contains(&self.places(index, d as u8).len())).collect();
+
+            for subset in combinations(&open, count) {
+                let mut cells: Vec<usize> = subset.iter().flat_map(|&d| self.places(index, d as u8)).collect();
+
+                cells.sort();
+                cells.dedup();
+
+                if cells.len() != count {
+                    continue;
+                }
+
+                let digits = subset.iter().fold(0u32, |mask, &d| mask | 1 << d);
+
+                let eliminated = self.candidates_in(cells.iter().copied(), !digits);
+
+                if !eliminated.is_empty() {
+                    return Some(Step { technique, units: vec![index], cells, digits: digits_of(digits), placed: vec![], eliminated });
+                }
+            }
+        }
+
+        return None;
+    }
+
+    // When a digit fits in `count` rows only within the same `count` columns, each of those columns must take it in one
+    // of those rows, so it goes nowhere else in them. Also checked with rows and columns swapped.
+    fn fish(&self, count: usize, technique: Technique) -> Option<Step> {
+        let size = self.rules.size;
+
+        for digit in 1..=size as u8 {
+            for base in 0..2 {
+                let lines: Vec<usize> = (0..size).filter(|&i| (2..=count).contains(&self.places(2 * i + base, digit).len())).collect();
+
+                for subset in combinations(&lines, count) {
+                    let cells: Vec<usize> = subset.iter().flat_map(|&i| self.places(2 * i + base, digit)).collect();
+
+                    let mut covers: Vec<usize> = cells.iter().map(|&cell| if base == 0 { cell % size } else { cell / size }).collect();
+
+                    covers.sort();
+                    covers.dedup();
+
+                    if covers.len() != count {
+                        continue;
+                    }
+
+                    let eliminated = self.candidates_in(covers.iter().flat_map(|&i| self.places(2 * i + 1 - base, digit)).filter(|cell| !cells.contains(cell)), 1 << digit);
+
+                    if !eliminated.is_empty() {
+                        let units = subset.iter().map(|&i| 2 * i + base).collect();
+
+                        return Some(Step { technique, units, cells, digits: vec![digit], placed: vec![], eliminated });
+                    }
+                }
+            }
+        }
+
+        return None;
+    }
+
+    // A pivot with candidates {x, y} seeing wings {x, z} and {y, z}: one of the wings must be z.
+    fn xy_wing(&self) -> Option<Step> {
+        let pairs: Vec<usize> = (0..self.cells.len()).filter(|&cell| self.candidates[cell].count_ones() == 2).collect();
+
+        for &pivot in &pairs {
+            for &a in &pairs {
+                for &b in &pairs {
+                    if a >= b || !self.rules.sees(pivot, a) || !self.rules.sees(pivot, b) {
+                        continue;
+                    }
+
+                    let (p, x, y) = (self.candidates[pivot], self.candidates[a], self.candidates[b]);
+
+                    let z = x & y;
+
+                    if z.count_ones() != 1 || z & p != 0 || (x | y) & !z != p {
+                        continue;
+                    }
+
+                    let eliminated = self.candidates_in((0..self.cells.len()).filter(|&cell| cell != pivot && self.rules.sees(cell, a) && self.rules.sees(cell, b)), z);
+
+                    if !eliminated.is_empty() {
+                        return Some(Step { technique: Technique::XYWing, units: vec![], cells: vec![pivot, a, b], digits: digits_of(z), placed: vec![], eliminated });
+                    }
+                }
+            }
+        }
+
+        return None;
+    }
+
+    // The easiest deduction available right now.
+    fn next_step(&self) -> Option<Step> {
+        return self
+            .hidden_single()
+            .or_else(|| self.naked_single())
+            .or_else(|| self.locked_candidates())
+            .or_else(|| self.naked_subset(2, Technique::NakedPair))
+            .or_else(|| self.hidden_subset(2, Technique::HiddenPair))
+            .or_else(|| self.naked_subset(3, Technique::NakedTriple))
+            .or_else(|| self.hidden_subset(3, Technique::HiddenTriple))
+            .or_else(|| self.fish(2, Technique::XWing))
+            .or_else(|| self.fish(3, Technique::Swordfish))
+            .or_else(|| self.xy_wing());
+    }
+
+    // Applies deductions until the grid is solved or none apply.
+    fn run(&mut self) -> Vec<Step> {
+        let mut steps = vec![];
+
+        while !self.is_solved() {
+            match self.next_step() {
+                Some(step) => {
+                    self.apply(&step);
+
+                    steps.push(step);
+                }
+                None => break,
+            }
+        }
+
+        return steps;
+    }
+}
+
+// Every step from the puzzle to its solution, easiest deduction first. Whenever logic stalls, the cell with the fewest
+// candidates is filled in from a solution found by search. None when the board is invalid or unsolvable.
+fn hints(rules: &Rules, board: &Board) -> Option<Vec<Step>> {
+    let solution = rules.solve(board)?.cells;
+
+    let mut logic = Logic::new(rules, board)?;
+
+    let mut steps = vec![];
+
+    loop {
+        steps.extend(logic.run());
+
+        if logic.is_solved() {
+            return Some(steps);
+        }
+
+        let cell = (0..solution.len()).filter(|&cell| logic.cells[cell] == 0).min_by_key(|&cell| logic.candidates[cell].count_ones())?;
+
+        let step = Step { technique: Technique::Guess, units: vec![], cells: vec![cell], digits: vec![solution[cell]], placed: vec![(cell, solution[cell])], eliminated: vec![] };
+
+        logic.apply(&step);
+
+        steps.push(step);
+    }
+}
+
+fn combinations(items: &[usize], count: usize) -> Vec<Vec<usize>> {
+    if count == 0 {
+        return vec![vec![]];
+    }
+
+    let mut result = vec![];
+
+    for (i, &item) in items.iter().enumerate() {
+        for mut rest in combinations(&items[i + 1..], count - 1) {
+            rest.insert(0, item);
+
+            result.push(rest);
+        }
+    }
+
+    return result;
+}
+
+// The hardest technique a person needs, or Expert when the known techniques stall. None for an invalid board.
+fn grade(rules: &Rules, board: &Board) -> Option<Difficulty> {
+    let mut logic = Logic::new(rules, board)?;
+
+    let steps = logic.run();
+
+    if !logic.is_solved() {
+        return Some(Difficulty::Expert);
+    }
+
+    return Some(steps.iter().map(|step| step.technique.difficulty()).max().unwrap_or(Difficulty::Easy));
+}
+
+// xorshift64*, small and good enough to make puzzles reproducible from a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        return Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1);
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+
+        return self.0.wrapping_mul(0x2545_f491_4f6c_dd1d);
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        return (self.next() % n as u64) as usize;
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) -> () {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+// Searches the empty grid trying digits in random order, so every seed gives a different complete grid. Cages can
+// make even the empty grid unsolvable, which gives `None`.
+fn random_solution(rules: &Rules, rng: &mut Rng) -> Option<Board> {
+    let mut solutions = rules.solutions(&Board::empty(rules.size));
+
+    solutions.rng = Some(Rng::new(rng.next()));
+
+    return solutions.next();
+}
+
+// Removes clues in point-symmetric pairs as long as the puzzle stays unique and no harder than asked for, and retries
+// with a fresh solution until the grade matches exactly. The same seed always gives the same puzzle.
+fn generate(rules: &Rules, seed: u64, difficulty: Difficulty) -> Option<Board> {
+    let size = rules.size;
+
+    let mut rng = Rng::new(seed);
+
+    for _ in 0..100 {
+        let mut board = random_solution(rules, &mut rng)?;
+
+        let mut cells: Vec<usize> = (0..size * size).collect();
+
+        rng.shuffle(&mut cells);
+
+        for cell in cells {
+            let twin = size * size - 1 - cell;
+
+            let kept = (board.cells[cell], board.cells[twin]);
+
+            if kept.0 == 0 {
+                continue;
+            }
+
+            board.cells[cell] = 0;
+            board.cells[twin] = 0;
+
+            if !rules.has_unique_solution(&board) || grade(rules, &board).unwrap() > difficulty {
+                board.cells[cell] = kept.0;
+                board.cells[twin] = kept.1;
+            }
+        }
+
+        if grade(rules, &board) == Some(difficulty) {
+            return Some(board);
+        }
+    }
+
+    return None;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PencilError {
+    Given { cell: String },
+    Filled { cell: String },
+    DigitOutOfRange { digit: u8, size: usize },
+    CellOutOfRange { cell: usize, size: usize },
+    NoSolution,
+    MultipleSolutions,
+}
+
+impl fmt::Display for PencilError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PencilError::Given { cell } => write!(f, "{} is a given and can't be changed", cell),
+            PencilError::Filled { cell } => write!(f, "{} is filled in, clear it before marking it", cell),
+            PencilError::DigitOutOfRange { digit, size } => write!(f, "digit {} doesn't fit a {}x{} grid", digit, size, size),
+            PencilError::CellOutOfRange { cell, size } => write!(f, "cell {} is outside a {}x{} grid", cell, size, size),
+            PencilError::NoSolution => write!(f, "the puzzle has no solution"),
+            PencilError::MultipleSolutions => write!(f, "the puzzle has more than one solution"),
+        }
+    }
+}
+
+// The state of a puzzle being worked on by hand: the givens, the digits the player has filled in and the pencil marks
+// they keep for every empty cell. Marks start out as the candidates the rules allow and are only changed by the player,
+// so they can go stale or wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PencilGrid {
+    rules: Rules,
+    givens: Board,
+    cells: Vec<u8>,
+    marks: Vec<u32>,
+}
+
+impl PencilGrid {
+    // None when the givens clash under the rules or were checked against rules of another size.
+    fn new(rules: &Rules, givens: &Board) -> Option<PencilGrid> {
+        Grid::from_board(rules, givens)?;
+
+        let mut pencil = PencilGrid { rules: rules.clone(), givens: givens.clone(), cells: givens.cells.clone(), marks: vec![] };
+
+        pencil.marks = pencil.candidates();
+
+        return Some(pencil);
+    }
+
+    fn board(&self) -> Board {
+        return Board { size: self.rules.size, cells: self.cells.clone() };
+    }
+
+    // Digits each empty cell can take given everything filled in so far, 0 for filled cells. Unlike `Grid` this copes
+    // with a player's clashing digits, a cell just loses every digit any of its peers holds.
+    fn candidates(&self) -> Vec<u32> {
+        let rules = &self.rules;
+
+        let mut candidates: Vec<u32> = self.cells.iter().map(|&digit| if digit == 0 { rules.all_digits() } else { 0 }).collect();
+
+        for (cell, &digit) in self.cells.iter().enumerate() {
+            if digit == 0 {
+                continue;
+            }
+
+            for (peer, mask) in candidates.iter_mut().enumerate() {
+                if rules.sees(cell, peer) {
+                    *mask &= !(1 << digit);
+                }
+            }
+        }
+
+        // Cage sums need the whole cage at once.
+        for cage in &rules.cages {
+            let placed = cage.cells.iter().fold(0, |mask, &cell| mask | (1u32 << self.cells[cell]) & !1);
+
+            for &cell in &cage.cells {
+                candidates[cell] &= cage.allowed(placed);
+            }
+        }
+
+        return candidates;
+    }
+
+    fn check_digit(&self, digit: u8) -> Result<(), PencilError> {
+        if digit == 0 || digit as usize > self.rules.size {
+            return Err(PencilError::DigitOutOfRange { digit, size: self.rules.size });
+        }
+
+        return Ok(());
+    }
+
+    fn check_cell(&self, cell: usize) -> Result<(), PencilError> {
+        if cell >= self.cells.len() {
+            return Err(PencilError::CellOutOfRange { cell, size: self.rules.size });
+        }
+
+        return Ok(());
+    }
+
+    // Fills in a digit, or clears the cell with 0. Marks of the same digit in the cell's peers are removed the way a
+    // player would, the cell's own marks are kept for when it's cleared again.
+    fn set(&mut self, cell: usize, digit: u8) -> Result<(), PencilError> {
+        self.check_cell(cell)?;
+
+        if self.givens.cells[cell] != 0 {
+            return Err(PencilError::Given { cell: self.rules.cell_name(cell) });
+        }
+
+        if digit != 0 {
+            self.check_digit(digit)?;
+
+            for peer in 0..self.cells.len() {
+                if self.rules.sees(cell, peer) {
+                    self.marks[peer] &= !(1 << digit);
+                }
+            }
         }
+
+        self.cells[cell] = digit;
+
+        return Ok(());
+    }
+
+    // Flips one pencil mark and returns whether it's now set.
+    fn toggle(&mut self, cell: usize, digit: u8) -> Result<bool, PencilError> {
+        self.check_cell(cell)?;
+        self.check_digit(digit)?;
+
+        if self.cells[cell] != 0 {
+            return Err(PencilError::Filled { cell: self.rules.cell_name(cell) });
+        }
+
+        self.marks[cell] ^= 1 << digit;
+
+        return Ok(self.marks[cell] & 1 << digit != 0);
+    }
+
+    // Throws away the player's marks and starts again from the candidates.
+    fn reset_marks(&mut self) -> () {
+        self.marks = self.candidates();
+    }
+
+    // Empty cells left with nothing to write in, because every digit either clashes with a filled peer or has had its
+    // mark removed.
+    fn contradictions(&self) -> Vec<usize> {
+        let candidates = self.candidates();
+
+        return (0..self.cells.len()).filter(|&cell| self.cells[cell] == 0 && self.marks[cell] & candidates[cell] == 0).collect();
+    }
+
+    // Filled cells that disagree with the puzzle's one solution. Only meaningful for a proper puzzle, so the givens must
+    // have exactly one solution.
+    fn mistakes(&self) -> Result<Vec<usize>, PencilError> {
+        let mut solutions = self.rules.solutions(&self.givens);
+
+        let solution = match (solutions.next(), solutions.next()) {
+            (Some(solution), None) => solution,
+            (None, _) => return Err(PencilError::NoSolution),
+            (Some(_), Some(_)) => return Err(PencilError::MultipleSolutions),
+        };
+
+        return Ok((0..self.cells.len()).filter(|&cell| self.cells[cell] != 0 && self.cells[cell] != solution.cells[cell]).collect());
+    }
+
+    // One line per row, filled cells as `=d` and empty ones as their marks, each padded to the widest cell.
+    fn format(&self) -> String {
+        let text: Vec<String> = (0..self.cells.len())
+            .map(|cell| match self.cells[cell] {
+                0 => digits_of(self.marks[cell]).into_iter().map(|digit| value_symbol(digit as i32)).collect(),
+                digit => format!("={}", value_symbol(digit as i32)),
+            })
+            .collect();
+
+        let width = text.iter().map(|cell| cell.len()).max().unwrap_or(0);
+
+        return text
+            .chunks(self.rules.size)
+            .map(|row| row.iter().map(|cell| format!("{:<width$}", cell, width = width)).collect::<Vec<String>>().join(" ").trim_end().to_string() + "\n")
+            .collect();
+    }
+}
+
+// How one puzzle of a batch went. A puzzle with several solutions still carries the first one found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Outcome {
+    Solved(Board),
+    Multiple(Board),
+    Unsolvable,
+    Invalid(BoardError),
+}
+
+#[derive(Debug, Clone)]
+struct BatchResult {
+    outcome: Outcome,
+    elapsed: Duration,
+}
+
+fn solve_one(rules: &Rules, rows: &[Vec<i32>], engine: &dyn Engine) -> Outcome {
+    let board = match Board::new(rules, rows) {
+        Ok(board) => board,
+        Err(err) => return Outcome::Invalid(err),
+    };
+
+    let mut solutions = engine.solutions(rules, &board);
+
+    return match (solutions.next(), solutions.next()) {
+        (Some(first), None) => Outcome::Solved(first),
+        (Some(first), Some(_)) => Outcome::Multiple(first),
+        (None, _) => Outcome::Unsolvable,
+    };
+}
+
+// Workers take puzzles one at a time from a shared counter, so a few slow puzzles don't hold up a whole thread's share.
+// Results come back in input order whatever order they finish in.
+fn solve_batch(rules: &Rules, puzzles: &[Vec<Vec<i32>>], threads: usize, engine: &(dyn Engine + Sync)) -> Vec<BatchResult> {
+    let next = AtomicUsize::new(0);
+
+    let finished: Vec<Vec<(usize, BatchResult)>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+
+                        if i >= puzzles.len() {
+                            return done;
+                        }
+
+                        let start = Instant::now();
+
+                        let outcome = solve_one(rules, &puzzles[i], engine);
+
+                        done.push((i, BatchResult { outcome, elapsed: start.elapsed() }));
+                    }
+                })
+            })
+            .collect();
+
+        return workers.into_iter().map(|worker| worker.join().unwrap()).collect();
+    });
+
+    let mut results: Vec<Option<BatchResult>> = vec![None; puzzles.len()];
+
+    for (i, result) in finished.into_iter().flatten() {
+        results[i] = Some(result);
     }
 
-    return false;
+    return results.into_iter().map(|result| result.unwrap()).collect();
 }
 
-fn is_in_col(board: Vec<Vec<i32>>, number: i32, col: i32) -> bool {
-    for i in 0..GRID_SIZE {
-        if board[i as usize][col as usize] == number {
-            return true;
-        }
+// Totals for a finished batch, `elapsed` being the wall-clock time of the whole run.
+fn batch_summary(results: &[BatchResult], threads: usize, elapsed: Duration) -> String {
+    let count = |matches: fn(&Outcome) -> bool| results.iter().filter(|result| matches(&result.outcome)).count();
+
+    let solved = count(|outcome| matches!(outcome, Outcome::Solved(_)));
+    let multiple = count(|outcome| matches!(outcome, Outcome::Multiple(_)));
+    let unsolvable = count(|outcome| matches!(outcome, Outcome::Unsolvable));
+    let invalid = count(|outcome| matches!(outcome, Outcome::Invalid(_)));
+
+    let busy: Duration = results.iter().map(|result| result.elapsed).sum();
+
+    let mut text = format!(
+        "{} puzzles on {} {} in {:?}, {:.0} puzzles/s: {} solved, {} with multiple solutions, {} unsolvable, {} invalid",
+        results.len(),
+        threads,
+        if threads == 1 { "thread" } else { "threads" },
+        elapsed,
+        results.len() as f64 / elapsed.as_secs_f64(),
+        solved,
+        multiple,
+        unsolvable,
+        invalid
+    );
+
+    if let Some((i, slowest)) = results.iter().enumerate().max_by_key(|(_, result)| result.elapsed) {
+        text += &format!("; {:?} solving on average, slowest is puzzle {} at {:?}", busy / results.len() as u32, i + 1, slowest.elapsed);
     }
 
-    return false;
+    return text;
 }
 
-fn is_in_box(board: Vec<Vec<i32>>, number: i32, row: i32, col: i32) -> bool {
-    let localrow = row - row % 3;
-    let localcol = col - col % 3;
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // `batch FILE [THREADS] [dlx]` solves across all cores with backtracking by default. Solutions go to stdout in input
+    // order, one line per puzzle, while timings, failures and the totals go to stderr.
+    if args.len() >= 2 && args[0] == "batch" {
+        let text = std::fs::read_to_string(&args[1]).expect("unable to read the batch file");
 
-    for i in localrow..localrow + 3 {
-        for j in localcol..localcol + 3 {
-            if board[i as usize][j as usize] == number {
-                return true;
+        let puzzles = match parse_batch(&text) {
+            Ok(puzzles) => puzzles,
+            Err(err) => {
+                eprintln!("{}", err);
+
+                return;
             }
+        };
+
+        let threads = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or_else(|| thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+
+        let engine: &(dyn Engine + Sync) = if args.get(3).map(|arg| arg.as_str()) == Some("dlx") { &DancingLinks } else { &Backtracking };
+
+        // One set of rules covers the whole batch, so every puzzle has to be the size of the first.
+        let size = puzzles.first().map_or(9, |rows| rows.len());
+
+        if let Some(i) = puzzles.iter().position(|rows| rows.len() != size) {
+            eprintln!("puzzle {} is {}x{} but the batch started with a {}x{} puzzle", i + 1, puzzles[i].len(), puzzles[i].len(), size, size);
+
+            return;
         }
-    }
 
-    return false;
-}
+        let rules = match Rules::for_size(size) {
+            Some(rules) => rules,
+            None => {
+                eprintln!("a {}x{} grid can't be split into boxes", size, size);
 
-fn is_valid_placement(board: Vec<Vec<i32>>, number: i32, row: i32, col: i32) -> bool {
-    return !is_in_row(board.clone(), number, row) && !is_in_col(board.clone(), number, col) && !is_in_box(board.clone(), number, row, col);
-}
+                return;
+            }
+        };
 
-fn can_be_solved(mut board: Vec<Vec<i32>>) -> bool {
-    for row in 0..GRID_SIZE {
-        for col in 0..GRID_SIZE {
-            if board[row as usize][col as usize] == 0 {
-                for attempt in 1..GRID_SIZE + 1 {
-                    if is_valid_placement(board.clone(), attempt, row, col) {
-                        board[row as usize][col as usize] = attempt;
+        let start = Instant::now();
 
-                        if can_be_solved(board.clone()) {
-                            return true;
-                        } else {
-                            board[row as usize][col as usize] = 0;
-                        }
-                    }
-                }
+        let results = solve_batch(&rules, &puzzles, threads, engine);
+
+        let elapsed = start.elapsed();
 
-                return false;
+        for (i, result) in results.iter().enumerate() {
+            match &result.outcome {
+                Outcome::Solved(solved) => {
+                    println!("{}", format_line(solved));
+                    eprintln!("puzzle {}: solved in {:?}", i + 1, result.elapsed);
+                }
+                Outcome::Multiple(first) => {
+                    println!("{}", format_line(first));
+                    eprintln!("puzzle {}: multiple solutions, found in {:?}", i + 1, result.elapsed);
+                }
+                Outcome::Unsolvable => {
+                    println!("unsolvable");
+                    eprintln!("puzzle {}: unsolvable, found in {:?}", i + 1, result.elapsed);
+                }
+                Outcome::Invalid(err) => {
+                    println!("invalid");
+                    eprintln!("puzzle {}: {}", i + 1, err);
+                }
             }
         }
+
+        eprintln!("{}", batch_summary(&results, threads, elapsed));
+
+        return;
     }
 
-    return true;
-}
+    // Solves a batch file given on the command line, printing one solution per line or the reason there isn't one.
+    if let Some(path) = args.first() {
+        let text = std::fs::read_to_string(path).expect("unable to read the batch file");
 
-fn solve(board: &mut Vec<Vec<i32>>) -> Option<Vec<Vec<i32>>> {
-    for row in 0..GRID_SIZE {
-        for col in 0..GRID_SIZE {
-            if board[row as usize][col as usize] == 0 {
-                for attempt in 1..GRID_SIZE + 1 {
-                    if is_valid_placement(board.clone(), attempt, row, col) {
-                        board[row as usize][col as usize] = attempt;
+        match parse_batch(&text) {
+            Ok(puzzles) => {
+                for rows in puzzles {
+                    let rules = match Rules::for_size(rows.len()) {
+                        Some(rules) => rules,
+                        None => {
+                            println!("a {}x{} grid can't be split into boxes", rows.len(), rows.len());
 
-                        if !solve(board).is_none() {
-                            return Some(board.to_vec());
-                        } else {
-                            board[row as usize][col as usize] = 0;
+                            continue;
                         }
+                    };
+
+                    match Board::new(&rules, &rows).map(|board| rules.solve(&board)) {
+                        Ok(Some(solved)) => println!("{}", format_line(&solved)),
+                        Ok(None) => println!("no solution"),
+                        Err(err) => println!("{}", err),
                     }
                 }
-
-                return None;
             }
+            Err(err) => println!("{}", err),
         }
-    }
 
-    return Some(board.to_vec());
-}
+        return;
+    }
 
-fn main() {
-    let mut board: Vec<Vec<i32>> = vec![
+    let rows: Vec<Vec<i32>> = vec![
         vec![7, 0, 2, 0, 5, 0, 6, 0, 0],
         vec![0, 0, 0, 0, 0, 3, 0, 0, 0],
         vec![1, 0, 0, 0, 0, 9, 5, 0, 0],
@@ -102,7 +2146,524 @@ fn main() {
         vec![0, 0, 7, 0, 4, 0, 2, 0, 3],
     ];
 
-    println!("{:?}", can_be_solved(board.clone()));
-    println!("{:?}", solve(&mut board));
-    println!("{:?}", board);
+    let board = Board::new(&Rules::classic(), &rows).unwrap();
+
+    println!("{:?}", can_be_solved(&board));
+    println!("{:?}", solve(&board).map(|solved| solved.rows()));
+
+    // Built to defeat naive backtracking, the first row is empty and the clues favour high digits.
+    let hard: Vec<Vec<i32>> = vec![
+        vec![0, 0, 0, 0, 0, 0, 0, 0, 0],
+        vec![0, 0, 0, 0, 0, 3, 0, 8, 5],
+        vec![0, 0, 1, 0, 2, 0, 0, 0, 0],
+        vec![0, 0, 0, 5, 0, 7, 0, 0, 0],
+        vec![0, 0, 4, 0, 0, 0, 1, 0, 0],
+        vec![0, 9, 0, 0, 0, 0, 0, 0, 0],
+        vec![5, 0, 0, 0, 0, 0, 0, 7, 3],
+        vec![0, 0, 2, 0, 1, 0, 0, 0, 0],
+        vec![0, 0, 0, 0, 4, 0, 0, 0, 9],
+    ];
+
+    let puzzle = Board::new(&Rules::classic(), &hard).unwrap();
+
+    let start = Instant::now();
+
+    println!("{:?} in {:?}", solve(&puzzle).is_some(), start.elapsed());
+
+    println!("{:?}", has_unique_solution(&puzzle));
+
+    let (solved, stats) = solve_with_stats(&puzzle, false);
+
+    println!("{:?} {}", solved.is_some(), stats);
+
+    let (solvable, stats) = can_be_solved_with_stats(&board);
+
+    println!("{:?} {}", solvable, stats);
+
+    let (_, stats) = solve_with_stats(&board, true);
+
+    let trace = stats.trace.unwrap();
+
+    for event in trace.iter().filter(|event| !matches!(event, Event::Forced { .. } | Event::Undo { forced: true, .. })).take(8) {
+        println!("{}", event.describe(&Rules::classic()));
+    }
+
+    println!("{} events", trace.len());
+
+    let mut sparse = hard.clone();
+
+    sparse[0] = vec![0; 9];
+    sparse[1] = vec![0; 9];
+
+    let sparse = Board::new(&Rules::classic(), &sparse).unwrap();
+
+    println!("{:?} {:?}", has_unique_solution(&sparse), count_solutions(&sparse, 1000));
+
+    for solution in solutions(&sparse).take(2) {
+        println!("{:?}", solution.rows()[0]);
+    }
+
+    let mut broken = rows.clone();
+
+    broken[0][1] = 7;
+    broken[2][2] = 7;
+    broken[8][8] = 10;
+
+    println!("{}", Board::new(&Rules::classic(), &broken).unwrap_err());
+
+    broken[8][8] = 3;
+
+    println!("{}", Board::new(&Rules::classic(), &broken).unwrap_err());
+
+    println!("{}", Board::new(&Rules::classic(), &vec![vec![0; 9]; 4]).unwrap_err());
+
+    let line = "7.2.5.6.......3...1....95..8......9..43...75..9......8..97....5...2.......7.4.2.3";
+
+    let parsed = Board::new(&Rules::classic(), &parse_line(line).unwrap()).unwrap();
+
+    println!("{:?}", parsed.rows() == parse_grid(&format_grid(&parsed, &Rules::classic())).unwrap());
+
+    print!("{}", format_grid(&solve(&parsed).unwrap(), &Rules::classic()));
+
+    print!("{}", format_grid(&Board::new(&Rules::square(2), &parse_line("1.3...2.4.......").unwrap()).unwrap(), &Rules::square(2)));
+
+    println!("{:?}", parse_grid("1 . | 3 .\n. . | 2 .\n----+----\n4 . | . .\n. . | . .").unwrap());
+
+    for bad in ["1234567", "12.?.....", "1.5."] {
+        println!("{}", parse_line(bad).unwrap_err());
+    }
+
+    println!("{}", parse_grid("1 2\n3").unwrap_err());
+
+    match parse_batch("# two puzzles\n1.3...2.4....... first\n\n..3.4..1 second") {
+        Ok(puzzles) => println!("{} puzzles", puzzles.len()),
+        Err(err) => println!("{}", err),
+    }
+
+    for (name, rules) in [
+        ("4x4", Rules::square(2)),
+        ("6x6", Rules::new(2, 3)),
+        ("16x16", Rules::square(4)),
+        ("25x25", Rules::square(5)),
+        ("x", Rules::classic().with_diagonals()),
+        ("windoku", Rules::classic().with_windows()),
+    ] {
+        let start = Instant::now();
+
+        let solved = rules.solve(&Board::empty(rules.size)).unwrap();
+
+        println!("{} {:?} in {:?}", name, solved.rows()[0], start.elapsed());
+    }
+
+    for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard, Difficulty::Expert] {
+        let start = Instant::now();
+
+        let puzzle = generate(&Rules::classic(), 2024, difficulty).unwrap();
+
+        println!("{:?} {} clues {} in {:?}", difficulty, format_line(&puzzle), puzzle.clues(), start.elapsed());
+    }
+
+    println!("{:?}", generate(&Rules::classic(), 7, Difficulty::Hard) == generate(&Rules::classic(), 7, Difficulty::Hard));
+
+    println!("{:?}", grade(&Rules::classic(), &puzzle));
+
+    let expert = generate(&Rules::classic(), 2024, Difficulty::Expert).unwrap();
+
+    let steps = hints(&Rules::classic(), &expert).unwrap();
+
+    for step in steps.iter().filter(|step| step.technique >= Technique::LockedCandidates).take(6) {
+        println!("{}", step.explain(&Rules::classic()));
+    }
+
+    println!("{} steps, {} guesses", steps.len(), steps.iter().filter(|step| step.technique == Technique::Guess).count());
+
+    let regions: Vec<Vec<usize>> = [
+        "000111222",
+        "000111222",
+        "003111255",
+        "033444255",
+        "333444255",
+        "333744558",
+        "667774858",
+        "666778888",
+        "666677788",
+    ]
+    .iter()
+    .map(|row| row.bytes().map(|b| (b - b'0') as usize).collect())
+    .collect();
+
+    let jigsaw = Rules::jigsaw(&regions).unwrap();
+
+    let mut batch: Vec<Vec<Vec<i32>>> = (0..200).map(|seed| generate(&Rules::classic(), seed, Difficulty::Easy).unwrap().rows()).collect();
+
+    batch.push(sparse.rows());
+    batch.push(broken.clone());
+
+    // Valid givens, but nothing is left for r1c9.
+    let mut stuck = vec![vec![0; 9]; 9];
+
+    stuck[0] = vec![1, 2, 3, 4, 5, 6, 7, 8, 0];
+    stuck[1][8] = 9;
+
+    batch.push(stuck);
+
+    let mut outcomes = vec![];
+
+    for threads in [1, 4] {
+        let start = Instant::now();
+
+        let results = solve_batch(&Rules::classic(), &batch, threads, &Backtracking);
+
+        println!("{}", batch_summary(&results, threads, start.elapsed()));
+
+        outcomes.push(results.into_iter().map(|result| result.outcome).collect::<Vec<Outcome>>());
+    }
+
+    println!("{:?}", outcomes[0] == outcomes[1]);
+
+    println!("jigsaw {:?}", jigsaw.solve(&Board::empty(9)).map(|solved| solved.rows()[0].clone()));
+
+    // A killer puzzle with no givens at all, the cage sums alone pin down the grid.
+    let text = "
+        12: r1c2 r1c3
+        11: r1c4 r2c4
+        17: r1c5 r1c6 r1c7
+        14: r1c8 r2c7 r2c8 r3c8
+        26: r1c9 r2c9 r3c9 r4c9
+        15: r2c2 r2c3
+        19: r2c5 r2c6 r3c4 r3c5
+        11: r3c1 r3c2 r4c1
+        17: r3c3 r4c2 r4c3 r4c4
+        17: r3c6 r3c7 r4c6 r5c6
+        24: r4c5 r5c5 r6c5 r6c6 r7c6
+        16: r4c7 r4c8
+        26: r5c1 r6c1 r6c2 r7c2
+        18: r5c2 r5c3 r5c4
+        9: r5c7 r5c8
+        2: r5c9
+        11: r6c3 r6c4 r7c4 r8c4
+        17: r6c7 r7c7
+        22: r6c8 r7c8 r8c8 r9c8
+        17: r6c9 r7c9 r8c9 r9c9
+        30: r7c1 r8c1 r8c2 r9c1 r9c2
+        8: r7c3 r8c3
+        25: r7c5 r8c5 r8c6 r9c5 r9c6
+        3: r8c7 r9c7
+        11: r9c3 r9c4
+        ";
+
+    let killer = Rules::classic().with_cages(&parse_cages(text, 9).unwrap()).unwrap();
+
+    let start = Instant::now();
+
+    let solved = killer.solve(&Board::empty(9)).unwrap();
+
+    println!("killer {} {:?} in {:?}", format_line(&solved), killer.has_unique_solution(&Board::empty(9)), start.elapsed());
+
+    println!("{:?}", parse_cages(&format_cages(&killer), 9) == parse_cages(text, 9));
+
+    let mut givens = vec![vec![0; 9]; 9];
+
+    givens[2][2] = 5;
+    givens[3][1] = 5;
+
+    println!("{}", Board::new(&killer, &givens).unwrap_err());
+
+    for cages in [vec![(3, vec![0, 1]), (10, vec![1, 2])], vec![(40, vec![0, 1, 2])]] {
+        println!("{}", Rules::classic().with_cages(&cages).unwrap_err());
+    }
+
+    for bad in ["12: r1c1 r10c1", "x: r1c1"] {
+        println!("{}", parse_cages(bad, 9).unwrap_err());
+    }
+
+    let engines: Vec<Box<dyn Engine>> = vec![Box::new(Backtracking), Box::new(DancingLinks)];
+
+    // Dancing links only checks cage sums as it picks rows, so a bare killer grid is slow going for it. Giving it the
+    // top three rows keeps the comparison short.
+    let mut clued = Board::empty(9);
+
+    clued.cells[..27].copy_from_slice(&solved.cells[..27]);
+
+    for (name, rules, board) in [
+        ("hard", Rules::classic(), puzzle.clone()),
+        ("sparse", Rules::classic(), sparse.clone()),
+        ("windoku", Rules::classic().with_windows(), Board::empty(9)),
+        ("16x16", Rules::square(4), Board::empty(16)),
+        ("killer", killer.clone(), clued),
+    ] {
+        let mut found = vec![];
+
+        for engine in &engines {
+            let start = Instant::now();
+
+            let count = engine.count_solutions(&rules, &board, 1000);
+
+            println!("{} {}: {} solutions in {:?}", name, engine.name(), count, start.elapsed());
+
+            found.push((count, engine.solve(&rules, &board)));
+        }
+
+        // Engines may find solutions in a different order, only a unique one has to match.
+        println!("{} engines agree {:?}", name, found.iter().all(|(count, first)| *count == found[0].0 && (*count != 1 || *first == found[0].1)));
+    }
+
+    let mut pencil = PencilGrid::new(&Rules::classic(), &board).unwrap();
+
+    print!("{}", pencil.format());
+
+    // r1c2 can only be 3 or 8, the player rules out 3 and then wrongly fills r1c4 with 8.
+    println!("{:?} {:?}", pencil.toggle(1, 3), pencil.toggle(1, 3));
+
+    pencil.toggle(1, 3).unwrap();
+
+    pencil.set(3, 8).unwrap();
+
+    println!("{:?} {:?}", pencil.contradictions(), pencil.mistakes());
+
+    pencil.set(3, 4).unwrap();
+
+    pencil.reset_marks();
+
+    println!("{:?} {:?}", pencil.contradictions(), pencil.mistakes());
+
+    for err in [pencil.set(0, 1).unwrap_err(), pencil.toggle(3, 1).unwrap_err(), pencil.toggle(1, 10).unwrap_err(), pencil.toggle(81, 1).unwrap_err()] {
+        println!("{}", err);
+    }
+
+    println!("{}", PencilGrid::new(&Rules::classic(), &sparse).unwrap().mistakes().unwrap_err());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Both engines find the same number of solutions up to the limit, and the same set of them when there are fewer.
+    fn assert_engines_agree(rules: &Rules, board: &Board) -> () {
+        let limit = 50;
+
+        let mut found = vec![];
+
+        for engine in [&Backtracking as &dyn Engine, &DancingLinks] {
+            let mut solutions: Vec<Vec<u8>> = engine.solutions(rules, board).take(limit).map(|solved| solved.cells).collect();
+
+            solutions.sort();
+
+            found.push((solutions.len(), solutions, engine.solve(rules, board)));
+        }
+
+        let (backtracking, dancing_links) = (&found[0], &found[1]);
+
+        assert_eq!(backtracking.0, dancing_links.0, "solution counts differ for {}", format_line(board));
+
+        if backtracking.0 == 1 {
+            assert_eq!(backtracking.2, dancing_links.2, "unique solutions differ for {}", format_line(board));
+        }
+
+        if backtracking.0 < limit {
+            assert_eq!(backtracking.1, dancing_links.1, "solutions differ for {}", format_line(board));
+        }
+    }
+
+    // A random complete grid with all but `clues` cells emptied again, the same for the same seed.
+    fn puzzle(rules: &Rules, seed: u64, clues: usize) -> Board {
+        let mut rng = Rng::new(seed);
+
+        let mut board = random_solution(rules, &mut rng).unwrap();
+
+        let mut cells: Vec<usize> = (0..board.cells.len()).collect();
+
+        rng.shuffle(&mut cells);
+
+        for &cell in &cells[clues..] {
+            board.cells[cell] = 0;
+        }
+
+        return board;
+    }
+
+    fn assert_engines_agree_on_puzzles(rules: &Rules, clues: &[usize]) -> () {
+        for seed in 0..6 {
+            for &count in clues {
+                assert_engines_agree(rules, &puzzle(rules, seed, count));
+            }
+        }
+    }
+
+    #[test]
+    fn engines_agree_on_classic_puzzles() {
+        assert_engines_agree_on_puzzles(&Rules::classic(), &[81, 40, 30, 24, 20]);
+
+        for seed in 0..4 {
+            assert_engines_agree(&Rules::classic(), &generate(&Rules::classic(), seed, Difficulty::Medium).unwrap());
+        }
+    }
+
+    #[test]
+    fn engines_agree_on_unsolvable_boards() {
+        let mut stuck = Board::empty(9);
+
+        stuck.cells[..8].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        stuck.cells[17] = 9;
+
+        assert_engines_agree(&Rules::classic(), &stuck);
+
+        assert_eq!(Backtracking.count_solutions(&Rules::classic(), &stuck, 1), 0);
+
+        let small = Board::new(&Rules::square(2), &parse_line("123....4........").unwrap()).unwrap();
+
+        assert_engines_agree(&Rules::square(2), &small);
+
+        assert_eq!(Backtracking.count_solutions(&Rules::square(2), &small, 1), 0);
+    }
+
+    #[test]
+    fn engines_agree_on_variants() {
+        assert_engines_agree_on_puzzles(&Rules::classic().with_diagonals(), &[40, 28, 20]);
+        assert_engines_agree_on_puzzles(&Rules::classic().with_windows(), &[40, 28, 20]);
+        assert_engines_agree_on_puzzles(&Rules::new(2, 3), &[20, 12, 8, 0]);
+        assert_engines_agree_on_puzzles(&Rules::square(2), &[8, 4, 0]);
+
+        let regions: Vec<Vec<usize>> = ["000111222", "000111222", "003111255", "033444255", "333444255", "333744558", "667774858", "666778888", "666677788"]
+            .iter()
+            .map(|row| row.bytes().map(|b| (b - b'0') as usize).collect())
+            .collect();
+
+        assert_engines_agree_on_puzzles(&Rules::jigsaw(&regions).unwrap(), &[40, 28, 20]);
+    }
+
+    // Cages over pairs of neighbouring cells in a row, summed from a random solution so the puzzle stays solvable.
+    fn domino_cages(rules: &Rules, seed: u64) -> Rules {
+        let size = rules.size;
+
+        let solution = random_solution(rules, &mut Rng::new(seed)).unwrap();
+
+        let cages: Vec<(usize, Vec<usize>)> = (0..size * size)
+            .filter(|cell| (cell % size).is_multiple_of(2) && cell % size + 1 < size)
+            .map(|cell| ((solution.cells[cell] + solution.cells[cell + 1]) as usize, vec![cell, cell + 1]))
+            .collect();
+
+        return rules.clone().with_cages(&cages).unwrap();
+    }
+
+    #[test]
+    fn generate_gives_none_when_the_rules_have_no_solution() {
+        let rules = Rules::classic().with_cages(&[(3, vec![0, 1]), (3, vec![2, 3])]).unwrap();
+
+        assert_eq!(generate(&rules, 1, Difficulty::Easy), None);
+    }
+
+    #[test]
+    fn engines_agree_on_killer_puzzles() {
+        for seed in 0..6 {
+            assert_engines_agree_on_puzzles(&domino_cages(&Rules::square(2), seed), &[4, 0]);
+            assert_engines_agree_on_puzzles(&domino_cages(&Rules::new(2, 3), seed), &[12, 6, 0]);
+            assert_engines_agree_on_puzzles(&domino_cages(&Rules::classic(), seed), &[40, 28, 20]);
+        }
+    }
+
+    const KNOWN: &str = "7.2.5.6.......3...1....95..8......9..43...75..9......8..97....5...2.......7.4.2.3";
+
+    fn count(trace: &[Event], matches: fn(&Event) -> bool) -> usize {
+        return trace.iter().filter(|event| matches(event)).count();
+    }
+
+    #[test]
+    fn stats_count_the_search_on_a_known_puzzle() {
+        let board = Board::new(&Rules::classic(), &parse_line(KNOWN).unwrap()).unwrap();
+
+        let (solved, stats) = Rules::classic().solve_with_stats(&board, true);
+
+        assert!(solved.is_some());
+
+        assert_eq!((stats.nodes, stats.backtracks, stats.max_depth, stats.propagations), (13, 6, 6, 127));
+
+        let trace = stats.trace.unwrap();
+
+        assert_eq!(count(&trace, |event| matches!(event, Event::Guess { .. })), stats.nodes - 1);
+        assert_eq!(count(&trace, |event| matches!(event, Event::Forced { .. })), stats.propagations);
+        assert_eq!(count(&trace, |event| matches!(event, Event::Undo { forced: false, .. })), stats.backtracks);
+    }
+
+    // r1c4 = 1 leaves no solution, but only the search finds out. Once it runs dry every placement is taken back.
+    #[test]
+    fn stats_undo_every_placement_when_there_is_no_solution() {
+        let mut board = Board::new(&Rules::classic(), &parse_line(KNOWN).unwrap()).unwrap();
+
+        board.cells[3] = 1;
+
+        let (solved, stats) = Rules::classic().solve_with_stats(&board, true);
+
+        assert_eq!(solved, None);
+
+        assert_eq!((stats.nodes, stats.backtracks, stats.propagations), (11, 10, 67));
+
+        let trace = stats.trace.unwrap();
+
+        assert_eq!(count(&trace, |event| matches!(event, Event::Guess { .. })), stats.backtracks);
+        assert_eq!(count(&trace, |event| matches!(event, Event::Undo { forced: false, .. })), stats.backtracks);
+        assert_eq!(count(&trace, |event| matches!(event, Event::Forced { .. })), stats.propagations);
+        assert_eq!(count(&trace, |event| matches!(event, Event::Undo { forced: true, .. })), stats.propagations);
+    }
+
+    #[test]
+    fn pencil_rejects_cells_outside_the_grid() {
+        let mut pencil = PencilGrid::new(&Rules::classic(), &Board::empty(9)).unwrap();
+
+        assert_eq!(pencil.toggle(81, 1), Err(PencilError::CellOutOfRange { cell: 81, size: 9 }));
+        assert_eq!(pencil.set(81, 1), Err(PencilError::CellOutOfRange { cell: 81, size: 9 }));
+
+        assert_eq!(pencil.set(80, 1), Ok(()));
+        assert_eq!(pencil.toggle(79, 1), Ok(true));
+    }
+
+    #[test]
+    fn board_rejects_the_wrong_number_of_rows() {
+        assert_eq!(Board::new(&Rules::classic(), &vec![vec![0; 9]; 8]), Err(BoardError::RowCount { found: 8, expected: 9 }));
+    }
+
+    #[test]
+    fn board_rejects_a_short_row() {
+        let mut rows = vec![vec![0; 9]; 9];
+
+        rows[4].pop();
+
+        assert_eq!(Board::new(&Rules::classic(), &rows), Err(BoardError::RowLength { row: 4, found: 8, expected: 9 }));
+    }
+
+    #[test]
+    fn board_rejects_values_out_of_range() {
+        let mut rows = vec![vec![0; 4]; 4];
+
+        rows[1][2] = 5;
+
+        assert_eq!(Board::new(&Rules::square(2), &rows), Err(BoardError::OutOfRange { row: 1, col: 2, value: 5, size: 4 }));
+
+        rows[1][2] = -1;
+
+        assert_eq!(Board::new(&Rules::square(2), &rows), Err(BoardError::OutOfRange { row: 1, col: 2, value: -1, size: 4 }));
+    }
+
+    #[test]
+    fn board_reports_every_conflicting_pair() {
+        let rows = parse_line("1..1.1..........").unwrap();
+
+        let conflicts = vec![
+            Conflict { digit: 1, first: (0, 0), second: (0, 3), units: vec!["row 1".to_string()] },
+            Conflict { digit: 1, first: (0, 0), second: (1, 1), units: vec!["box 1".to_string()] },
+        ];
+
+        assert_eq!(Board::new(&Rules::square(2), &rows), Err(BoardError::Conflicts(conflicts)));
+    }
+
+    #[test]
+    fn board_reports_givens_repeated_in_a_cage() {
+        let rules = Rules::square(2).with_cages(&[(3, vec![1, 6])]).unwrap();
+
+        let rows = parse_line(".1....1.........").unwrap();
+
+        let conflicts = vec![Conflict { digit: 1, first: (0, 1), second: (1, 2), units: vec!["cage 1".to_string()] }];
+
+        assert_eq!(Board::new(&rules, &rows), Err(BoardError::Conflicts(conflicts)));
+    }
 }